@@ -0,0 +1,78 @@
+// DRW throughput, per-bit reference loop vs the masked fast path that DRW uses.
+// Run with `cargo bench --bench draw`; criterion reports time per batch of
+// SPRITES_PER_ITERATION sprites, and sprites per second. That both paths give identical
+// video and VF is checked by fast_draw_matches_naive_draw in src/chip8.rs, which
+// `cargo test` runs.
+use std::hint::black_box;
+
+use chip8::Chip8;
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+const SEED: u64 = 0xC8;
+const SPRITE_DATA_START: u16 = 0x300;
+const SPRITES_PER_ITERATION: usize = 256;
+
+struct Draw {
+    x: u8,
+    y: u8,
+    height: u8,
+    index: u16
+}
+
+fn random_draws(rng: &mut StdRng) -> Vec<Draw> {
+    (0..SPRITES_PER_ITERATION)
+        .map(|_| Draw {
+            x: rng.random(),            // deliberately past the edges too, to hit the clipping paths
+            y: rng.random(),
+            height: rng.random_range(1..=15),
+            index: SPRITE_DATA_START + rng.random_range(0..0x100),
+        })
+        .collect()
+}
+
+fn machine(rng: &mut StdRng, planes: u8) -> Chip8 {
+    let mut chip8 = Chip8::new();
+    chip8.selected_planes = planes;
+    for byte in chip8.memory[SPRITE_DATA_START as usize..SPRITE_DATA_START as usize + 0x200].iter_mut() {
+        *byte = rng.random();
+    }
+    chip8
+}
+
+fn draw(chip8: &mut Chip8, draw: &Draw, fast: bool) {
+    chip8.registers[0] = draw.x;
+    chip8.registers[1] = draw.y;
+    chip8.index = draw.index;
+
+    if fast {
+        chip8.draw_sprite_fast(0, 1, draw.height);
+    } else {
+        chip8.draw_sprite_naive(0, 1, draw.height);
+    }
+}
+
+fn bench(c: &mut Criterion) {
+    let mut group = c.benchmark_group("draw");
+    group.throughput(Throughput::Elements(SPRITES_PER_ITERATION as u64));
+
+    for (name, fast) in [("naive", false), ("fast", true)] {
+        let mut rng = StdRng::seed_from_u64(SEED);
+        let draws = random_draws(&mut rng);
+        let mut chip8 = machine(&mut rng, 1);
+
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                for d in &draws {
+                    draw(&mut chip8, d, fast);
+                }
+                black_box(&chip8.video);
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
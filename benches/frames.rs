@@ -0,0 +1,73 @@
+// Whole-machine throughput: a small draw-heavy ROM run headlessly through run_frame.
+// Run with `cargo bench --bench frames`; criterion reports time per frame, and
+// instructions per second as Melem/s.
+//
+// Baseline, measured on one vCPU of a 2.1GHz Xeon (Sapphire Rapids) KVM guest with rustc 1.95
+// and the default byte video, at 500 instructions per frame: frames/run_frame estimates of
+// 14.9-20.2 µs per frame (24.7-33.6 Melem/s, one element per instruction) over three runs.
+// Numbers only compare on the same machine; a large drop there points at the dispatch or
+// DRW hot paths.
+use std::hint::black_box;
+
+use chip8::{Chip8, RomBuilder, StopReason};
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+
+const SEED: u64 = 0xC8;
+const CYCLES_PER_FRAME: u32 = 500;
+const WARMUP_FRAMES: u32 = 100;
+
+// clears the screen, then walks an 8x8 sprite diagonally across it with some ALU work
+// per draw, starting over every 40 draws; roughly the instruction mix of a simple game
+fn workload() -> Vec<u8> {
+    let start = RomBuilder::new().address();
+    let setup = RomBuilder::new()
+        .cls()
+        .ld(2, 0)
+        .ld(3, 0)
+        .ld(5, 0x0F);
+    let draw_loop = setup.address();
+    let sprite = draw_loop + 20;
+
+    setup
+        .ld_i(sprite)
+        .drw(2, 3, 8)
+        .add(2, 3)
+        .add(3, 1)
+        .ld_reg(4, 2)
+        .and(4, 5)
+        .add_reg(4, 3)
+        .sne(3, 40)
+        .jp(start)
+        .jp(draw_loop)
+        .data(&[0x3C, 0x42, 0xA5, 0x81, 0xA5, 0x99, 0x42, 0x3C])
+        .build()
+}
+
+fn machine() -> Chip8 {
+    let rom = workload();
+    let mut chip8 = Chip8::with_seed(SEED);
+    chip8.memory[0x200..0x200 + rom.len()].copy_from_slice(&rom);
+    chip8.cycles_per_frame = CYCLES_PER_FRAME;
+    chip8
+}
+
+fn bench(c: &mut Criterion) {
+    let mut chip8 = machine();
+    for _ in 0..WARMUP_FRAMES {
+        chip8.run_frame();
+    }
+
+    let mut group = c.benchmark_group("frames");
+    group.throughput(Throughput::Elements(CYCLES_PER_FRAME as u64));
+    group.bench_function("run_frame", |b| {
+        b.iter(|| {
+            let outcome = chip8.run_frame();
+            assert_eq!(outcome.reason, StopReason::FrameComplete, "workload stopped: {}", outcome);
+            black_box(&chip8.video);
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -0,0 +1,78 @@
+// XO-CHIP sound: a 128-sample 1-bit waveform (F002 loads it from [I]) looped at a rate set by
+// the pitch register (Fx3A) for as long as the sound timer runs. Plain CHIP-8 ROMs never touch
+// either, so they get the default square wave, which sounds like the usual beep.
+
+pub const DEFAULT_PITCH: u8 = 64;   // 4000 samples per second
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AudioPattern {
+    pub bits: [u8; 16],             // MSB of bits[0] plays first
+    pub pitch: u8
+}
+
+impl Default for AudioPattern {
+    fn default() -> Self {
+        AudioPattern {
+            bits: [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0],
+            pitch: DEFAULT_PITCH
+        }
+    }
+}
+
+impl AudioPattern {
+    // pattern bits per second, 4000 * 2^((pitch - 64) / 48) as XO-CHIP defines it
+    pub fn playback_rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0)
+    }
+
+    pub fn bit(&self, index: usize) -> bool {
+        let index = index % 128;
+        self.bits[index / 8] & (0x80 >> (index % 8)) != 0
+    }
+
+    // fills `out` with the looping waveform as -1.0/1.0 samples at `sample_rate`; `phase` is the
+    // position in the pattern (in bits) and is carried across calls so buffers join up seamlessly
+    pub fn fill_samples(&self, out: &mut [f32], sample_rate: u32, phase: &mut f64) {
+        let step = self.playback_rate() / sample_rate as f64;
+        for sample in out.iter_mut() {
+            *sample = if self.bit(*phase as usize) { 1.0 } else { -1.0 };
+            *phase = (*phase + step) % 128.0;
+        }
+    }
+}
+
+// where the machine's sound goes; start is called again if the pattern or pitch changes
+// while sound is already playing
+pub trait SoundSink {
+    fn start(&mut self, pattern: &AudioPattern);
+    fn stop(&mut self);
+}
+
+// adapts a plain on/off closure to SoundSink for Chip8::on_sound, swallowing the extra start
+// calls a pattern change makes so the closure only sees real edges
+pub(crate) struct SoundCallback {
+    callback: Box<dyn FnMut(bool)>,
+    playing: bool
+}
+
+impl SoundCallback {
+    pub(crate) fn new(callback: Box<dyn FnMut(bool)>) -> Self {
+        SoundCallback { callback, playing: false }
+    }
+}
+
+impl SoundSink for SoundCallback {
+    fn start(&mut self, _pattern: &AudioPattern) {
+        if !self.playing {
+            self.playing = true;
+            (self.callback)(true);
+        }
+    }
+
+    fn stop(&mut self) {
+        if self.playing {
+            self.playing = false;
+            (self.callback)(false);
+        }
+    }
+}
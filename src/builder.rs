@@ -0,0 +1,102 @@
+use crate::chip8::START_ADDRESS;
+
+// typed, chainable way to put a ROM together without hand-encoding hex, e.g.
+// RomBuilder::new().ld(0, 5).add(0, 3).drw(0, 1, 4).halt().build()
+// Register arguments are V0-VF (only the low nibble is used), addresses are 12-bit.
+#[derive(Debug, Clone, Default)]
+pub struct RomBuilder {
+    bytes: Vec<u8>
+}
+
+impl RomBuilder {
+    pub fn new() -> Self {
+        RomBuilder { bytes: Vec::new() }
+    }
+
+    // where the next instruction will end up once loaded, for jump targets
+    pub fn address(&self) -> u16 {
+        START_ADDRESS + self.bytes.len() as u16
+    }
+
+    pub fn build(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn word(mut self, opcode: u16) -> Self {
+        self.bytes.extend_from_slice(&opcode.to_be_bytes());
+        self
+    }
+
+    // raw data such as sprites, not necessarily an even number of bytes
+    pub fn data(mut self, bytes: &[u8]) -> Self {
+        self.bytes.extend_from_slice(bytes);
+        self
+    }
+
+    fn xkk(self, high: u16, x: u8, kk: u8) -> Self {
+        self.word(high | (x as u16 & 0xF) << 8 | kk as u16)
+    }
+
+    fn xyn(self, high: u16, x: u8, y: u8, n: u8) -> Self {
+        self.word(high | (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4 | (n as u16 & 0xF))
+    }
+
+    fn nnn(self, high: u16, address: u16) -> Self {
+        self.word(high | (address & 0x0FFF))
+    }
+
+    pub fn cls(self) -> Self { self.word(0x00E0) }
+    pub fn ret(self) -> Self { self.word(0x00EE) }
+    pub fn jp(self, address: u16) -> Self { self.nnn(0x1000, address) }
+    pub fn call(self, address: u16) -> Self { self.nnn(0x2000, address) }
+    pub fn se(self, x: u8, kk: u8) -> Self { self.xkk(0x3000, x, kk) }
+    pub fn sne(self, x: u8, kk: u8) -> Self { self.xkk(0x4000, x, kk) }
+    pub fn se_reg(self, x: u8, y: u8) -> Self { self.xyn(0x5000, x, y, 0x0) }
+    pub fn ld(self, x: u8, kk: u8) -> Self { self.xkk(0x6000, x, kk) }
+    pub fn add(self, x: u8, kk: u8) -> Self { self.xkk(0x7000, x, kk) }
+    pub fn ld_reg(self, x: u8, y: u8) -> Self { self.xyn(0x8000, x, y, 0x0) }
+    pub fn or(self, x: u8, y: u8) -> Self { self.xyn(0x8000, x, y, 0x1) }
+    pub fn and(self, x: u8, y: u8) -> Self { self.xyn(0x8000, x, y, 0x2) }
+    pub fn xor(self, x: u8, y: u8) -> Self { self.xyn(0x8000, x, y, 0x3) }
+    pub fn add_reg(self, x: u8, y: u8) -> Self { self.xyn(0x8000, x, y, 0x4) }
+    pub fn sub(self, x: u8, y: u8) -> Self { self.xyn(0x8000, x, y, 0x5) }
+    pub fn shr(self, x: u8, y: u8) -> Self { self.xyn(0x8000, x, y, 0x6) }
+    pub fn subn(self, x: u8, y: u8) -> Self { self.xyn(0x8000, x, y, 0x7) }
+    pub fn shl(self, x: u8, y: u8) -> Self { self.xyn(0x8000, x, y, 0xE) }
+    pub fn sne_reg(self, x: u8, y: u8) -> Self { self.xyn(0x9000, x, y, 0x0) }
+    pub fn ld_i(self, address: u16) -> Self { self.nnn(0xA000, address) }
+    pub fn jp_v0(self, address: u16) -> Self { self.nnn(0xB000, address) }
+    pub fn rnd(self, x: u8, kk: u8) -> Self { self.xkk(0xC000, x, kk) }
+    pub fn drw(self, x: u8, y: u8, n: u8) -> Self { self.xyn(0xD000, x, y, n) }
+    pub fn skp(self, x: u8) -> Self { self.xkk(0xE000, x, 0x9E) }
+    pub fn sknp(self, x: u8) -> Self { self.xkk(0xE000, x, 0xA1) }
+    pub fn ld_from_dt(self, x: u8) -> Self { self.xkk(0xF000, x, 0x07) }
+    pub fn ld_key(self, x: u8) -> Self { self.xkk(0xF000, x, 0x0A) }
+    pub fn ld_dt(self, x: u8) -> Self { self.xkk(0xF000, x, 0x15) }
+    pub fn ld_st(self, x: u8) -> Self { self.xkk(0xF000, x, 0x18) }
+    pub fn add_i(self, x: u8) -> Self { self.xkk(0xF000, x, 0x1E) }
+    pub fn ld_font(self, x: u8) -> Self { self.xkk(0xF000, x, 0x29) }
+    pub fn ld_bcd(self, x: u8) -> Self { self.xkk(0xF000, x, 0x33) }
+    pub fn store(self, x: u8) -> Self { self.xkk(0xF000, x, 0x55) }     // LD [I], V0..Vx
+    pub fn load(self, x: u8) -> Self { self.xkk(0xF000, x, 0x65) }      // LD V0..Vx, [I]
+
+    // jump to itself, which the interpreter treats as halting
+    pub fn halt(self) -> Self {
+        let address = self.address();
+        self.jp(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emits_the_documented_encoding() {
+        let rom = RomBuilder::new().ld(0, 5).add(0, 3).drw(0, 1, 4).halt().build();
+        assert_eq!(rom, [0x60, 0x05, 0x70, 0x03, 0xD0, 0x14, 0x12, 0x06]);
+
+        let rom = RomBuilder::new().call(0x345).se_reg(0xA, 0xB).sub(0x1F, 2).ld_i(0x1ABC).store(0xE).data(&[0xAA]).build();
+        assert_eq!(rom, [0x23, 0x45, 0x5A, 0xB0, 0x8F, 0x25, 0xAA, 0xBC, 0xFE, 0x55, 0xAA]);
+    }
+}
@@ -0,0 +1,40 @@
+use crate::chip8::InstructionSet;
+use crate::variant::Chip8Variant;
+
+// what this build of the emulator can do, so frontends don't have to guess from the version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    pub packed_video: bool,         // built with the `packed-video` feature, see src/video.rs
+    pub instruction_sets: Vec<InstructionSet>, // accepted by Chip8::instruction_set, oldest first
+    pub variants: Vec<Chip8Variant>, // presets for Chip8::set_variant
+}
+
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        packed_video: cfg!(feature = "packed-video"),
+        instruction_sets: vec![InstructionSet::Chip8, InstructionSet::SuperChip, InstructionSet::XoChip],
+        variants: vec![
+            Chip8Variant::Chip8,
+            Chip8Variant::SuperChip1_0,
+            Chip8Variant::SuperChip1_1,
+            Chip8Variant::XoChip,
+        ],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::video::{VIDEO_SIZE, Video};
+
+    #[test]
+    fn capabilities_match_the_build() {
+        let caps = capabilities();
+        assert_eq!(caps.packed_video, cfg!(feature = "packed-video"));
+        let video_bytes = if caps.packed_video { VIDEO_SIZE / 4 } else { VIDEO_SIZE };
+        assert_eq!(std::mem::size_of::<Video>(), video_bytes);
+
+        assert_eq!(caps.instruction_sets, [InstructionSet::Chip8, InstructionSet::SuperChip, InstructionSet::XoChip]);
+        assert!(caps.variants.iter().all(|variant| caps.instruction_sets.contains(&variant.instruction_set())));
+    }
+}
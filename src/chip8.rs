@@ -1,13 +1,39 @@
-use core::panic;
-use std::{fs, io::Read};
+use std::{collections::{BTreeMap, VecDeque}, fs, io::Read, time::Duration};
 
-use rand::Fill;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+
+use crate::audio::{AudioPattern, SoundCallback, SoundSink};
+use crate::debugger::{RunOutcome, StopReason};
+use crate::error::Chip8Error;
+use crate::history::{Capture, ExecutionHistory};
+use crate::input::InputSource;
+use crate::storage::{NullStorage, StorageBackend};
+use crate::timing::{SystemTimeSource, TimeSource};
+use crate::video::{PixelBuffer, VIDEO_SIZE, Video, blank_video};
 
 const MEMORY_SIZE: u16 = 4096;
-const VIDEO_WIDTH: u16 = 64;
-const VIDEO_HEIGHT: u16 = 32;
-const START_ADDRESS: u16 = 0x200;
+const LORES_WIDTH: usize = 64;
+const LORES_HEIGHT: usize = 32;
+const HIRES_WIDTH: usize = 128;         // SCHIP high resolution, also the size of the video buffer
+const HIRES_HEIGHT: usize = 64;
+pub(crate) const START_ADDRESS: u16 = 0x200;
 const FONTSET_START_ADDRESS: u16 = 0x50;
+const BIG_FONTSET_START_ADDRESS: u16 = 0xA0; // right after the small font
+const DEFAULT_CYCLES_PER_FRAME: u32 = 10;
+const DEFAULT_FRAME_RATE: f64 = 60.0;
+const TIMER_RATE: f64 = 60.0;             // delay and sound timers count down at 60Hz whatever frame_rate is
+const RPL_FLAGS_KEY: &str = "rpl_flags";
+const MAX_CATCH_UP_FRAMES: u32 = 8;  // beyond this we drop frames instead of fast-forwarding
+const CLOCK_SAMPLE_WINDOW: usize = 60; // frames measured_hz averages over
+
+// machine cycles, roughly the COSMAC VIP interpreter's; see machine_cycle_budget
+pub const COSMAC_VIP_CYCLES_PER_FRAME: u32 = 3668;
+const BASE_INSTRUCTION_COST: u32 = 68;
+const DRW_BASE_COST: u32 = 170;
+const DRW_ROW_COST: u32 = 68;
+const LOAD_STORE_BASE_COST: u32 = 68;
+const LOAD_STORE_REGISTER_COST: u32 = 28; // Fx55/Fx65, per register moved
 
 const CHIP8_FONTSET: [u8; 80] = [
     0xF0,0x90,0x90,0x90,0xF0,       // 0
@@ -28,6 +54,239 @@ const CHIP8_FONTSET: [u8; 80] = [
     0xF0,0x80,0xF0,0x80,0x80        // F
 ];
 
+// SCHIP 8x10 digits for Fx30; SCHIP 1.1 only had 0-9, A-F are the ones XO-CHIP (Octo) added
+const BIG_FONTSET: [u8; 160] = [
+    0xFF,0xFF,0xC3,0xC3,0xC3,0xC3,0xC3,0xC3,0xFF,0xFF, // 0
+    0x18,0x78,0x78,0x18,0x18,0x18,0x18,0x18,0xFF,0xFF, // 1
+    0xFF,0xFF,0x03,0x03,0xFF,0xFF,0xC0,0xC0,0xFF,0xFF, // 2
+    0xFF,0xFF,0x03,0x03,0xFF,0xFF,0x03,0x03,0xFF,0xFF, // 3
+    0xC3,0xC3,0xC3,0xC3,0xFF,0xFF,0x03,0x03,0x03,0x03, // 4
+    0xFF,0xFF,0xC0,0xC0,0xFF,0xFF,0x03,0x03,0xFF,0xFF, // 5
+    0xFF,0xFF,0xC0,0xC0,0xFF,0xFF,0xC3,0xC3,0xFF,0xFF, // 6
+    0xFF,0xFF,0x03,0x03,0x06,0x0C,0x18,0x18,0x18,0x18, // 7
+    0xFF,0xFF,0xC3,0xC3,0xFF,0xFF,0xC3,0xC3,0xFF,0xFF, // 8
+    0xFF,0xFF,0xC3,0xC3,0xFF,0xFF,0x03,0x03,0xFF,0xFF, // 9
+    0x7E,0xFF,0xC3,0xC3,0xC3,0xFF,0xFF,0xC3,0xC3,0xC3, // A
+    0xFC,0xFC,0xC3,0xC3,0xFC,0xFC,0xC3,0xC3,0xFC,0xFC, // B
+    0x3C,0xFF,0xC3,0xC0,0xC0,0xC0,0xC0,0xC3,0xFF,0x3C, // C
+    0xFC,0xFE,0xC3,0xC3,0xC3,0xC3,0xC3,0xC3,0xFE,0xFC, // D
+    0xFF,0xFF,0xC0,0xC0,0xFF,0xFF,0xC0,0xC0,0xFF,0xFF, // E
+    0xFF,0xFF,0xC0,0xC0,0xFF,0xFF,0xC0,0xC0,0xC0,0xC0  // F
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    SoundStart,                     // sound timer went from 0 to non-zero
+    SoundStop,                      // sound timer reached 0
+    VfImplicitWrite { pc: u16, opcode: u16 }, // VF clobbered by anything but LD VF, see watch_vf_writes
+    UnalignedSpriteRead { pc: u16, index: u16 }, // DRW with an odd I, see warn_on_unaligned_sprite_read
+    PcOutsideProgram { pc: u16 },   // fetched outside the loaded ROM, see warn_on_pc_outside_program
+    UnmatchedRet { pc: u16 },       // RET with no CALL left to return from, or through a frame no CALL pushed; see check_call_ret_pairing
+    MisalignedCall { pc: u16, target: u16 }, // CALL to an odd address, see check_call_ret_pairing
+    StorageWriteFailed { pc: u16 }, // Fx75 couldn't persist the RPL flags; they still hold for this run
+}
+
+// Event without its payload, for matching on what happened rather than where
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum EventKind {
+    SoundStart,
+    SoundStop,
+    VfImplicitWrite,
+    UnalignedSpriteRead,
+    PcOutsideProgram,
+    UnmatchedRet,
+    MisalignedCall,
+    StorageWriteFailed,
+}
+
+impl Event {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::SoundStart => EventKind::SoundStart,
+            Event::SoundStop => EventKind::SoundStop,
+            Event::VfImplicitWrite { .. } => EventKind::VfImplicitWrite,
+            Event::UnalignedSpriteRead { .. } => EventKind::UnalignedSpriteRead,
+            Event::PcOutsideProgram { .. } => EventKind::PcOutsideProgram,
+            Event::UnmatchedRet { .. } => EventKind::UnmatchedRet,
+            Event::MisalignedCall { .. } => EventKind::MisalignedCall,
+            Event::StorageWriteFailed { .. } => EventKind::StorageWriteFailed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color { r: 0x00, g: 0x00, b: 0x00 };
+    pub const WHITE: Color = Color { r: 0xFF, g: 0xFF, b: 0xFF };
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Color { r, g, b }
+    }
+}
+
+// indexed by pixel value: 0 = background, 1 = plane 1, 2 = plane 2, 3 = both planes
+const DEFAULT_PALETTE: [Color; 4] = [
+    Color::BLACK,
+    Color::WHITE,
+    Color::new(0xAA, 0xAA, 0xAA),
+    Color::new(0x55, 0x55, 0x55),
+];
+
+// what to do when an instruction fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    #[default]
+    Lenient,                        // halt the machine and keep going
+    Strict,                         // hand the error back to the caller
+}
+
+// which opcode extensions the interpreter accepts, each one a superset of the previous
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum InstructionSet {
+    #[default]
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+// how far Fx55/Fx65 move I after storing/loading V0..Vx
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IndexIncrement {
+    #[default]
+    XPlusOne,                       // COSMAC VIP, XO-CHIP
+    X,                              // SCHIP 1.0
+    None,                           // SCHIP 1.1
+}
+
+// behaviours that differ between interpreters; the defaults are the classic CHIP-8 ones except
+// shift_vy, which is off so 8xy6/8xyE keep shifting Vx in place, and display_wait and
+// load_store_clears_vf, which are off so nothing stalls or clobbers VF (set_variant(Chip8)
+// turns all three on)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuirkProfile {
+    pub display_wait: bool,         // DRW waits for the next 60Hz frame once something was drawn
+    pub clip_sprites: bool,         // sprites are cut at the screen edge instead of wrapping around
+    pub shift_vy: bool,             // 8xy6/8xyE shift Vy into Vx rather than Vx in place
+    pub load_store_increment: IndexIncrement,
+    pub jump_vx: bool,              // Bnnn jumps to nnn + Vx (x = top nibble of nnn) rather than nnn + V0
+    pub load_store_clears_vf: bool, // Fx55/Fx65 leave 0 in VF afterwards, as on the COSMAC VIP
+    pub add_i_overflow_flag: bool,  // Fx1E sets VF to 1 when I goes past 0xFFF, 0 otherwise (Amiga interpreter)
+}
+
+impl Default for QuirkProfile {
+    fn default() -> Self {
+        QuirkProfile {
+            display_wait: false,
+            clip_sprites: true,
+            shift_vy: false,
+            load_store_increment: IndexIncrement::XPlusOne,
+            jump_vx: false,
+            load_store_clears_vf: false,
+            add_i_overflow_flag: false,
+        }
+    }
+}
+
+// what the loader writes into the word right after the ROM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RomPadding {
+    #[default]
+    None,                           // leave memory as is (normally zeros)
+    Opcode(u16),                    // e.g. an invalid opcode the error policy will catch
+    Halt,                           // a jump to itself, which halts the machine
+}
+
+// what memory, registers and video hold at power-on, before the font and ROM go in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FillPattern {
+    #[default]
+    Zero,
+    Byte(u8),
+    Random,                         // from the machine's seeded RNG, so a seed always gives the same garbage
+}
+
+// where a ROM went and how much room is left after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadReport {
+    pub start_address: u16,
+    pub bytes_loaded: usize,        // the whole ROM; load_rom* refuse one that doesn't fit
+    pub free_bytes: usize,          // between the end of the ROM and the top of memory
+}
+
+// what run_frame does once the machine has halted (JP to itself, 00FD, or an error)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HaltBehavior {
+    #[default]
+    Stop,                           // stay halted, frames only tick the timers
+    Restart,                        // reset() at the end of the frame it halted in
+    RestartAfterFrames(u32),        // reset() once this many frames have ended halted, for looping demos
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MachineStatus {
+    #[default]
+    Running,
+    Halted,
+    WaitingForKey(u8),              // blocked in Fx0A, the register the key will land in
+}
+
+// SPREAD_BITS[b] has byte i (little-endian) set to 1 when bit 7 - i of b is set,
+// i.e. one lane per sprite pixel in screen order
+#[cfg(not(feature = "packed-video"))]
+const SPREAD_BITS: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut byte = 0;
+    while byte < 256 {
+        let mut bit = 0;
+        while bit < 8 {
+            if byte & (0x80 >> bit) != 0 {
+                table[byte] |= 1 << (bit * 8);
+            }
+            bit += 1;
+        }
+        byte += 1;
+    }
+    table
+};
+
+// an opcode split into its fields, whichever of them the instruction uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodedInstruction {
+    pub address: u16,               // where it was fetched from
+    pub opcode: u16,
+    pub x: u8,
+    pub y: u8,
+    pub n: u8,
+    pub kk: u8,
+    pub nnn: u16,
+}
+
+impl DecodedInstruction {
+    pub fn new(address: u16, opcode: u16) -> Self {
+        DecodedInstruction {
+            address,
+            opcode,
+            x: ((opcode & 0x0F00) >> 8) as u8,
+            y: ((opcode & 0x00F0) >> 4) as u8,
+            n: (opcode & 0x000F) as u8,
+            kk: (opcode & 0x00FF) as u8,
+            nnn: opcode & 0x0FFF,
+        }
+    }
+}
+
+// called after every executed instruction, see set_post_cycle_hook
+pub type PostCycleHook = Box<dyn FnMut(&Chip8, &DecodedInstruction)>;
+
+// (video, width, height), see set_render_callback
+pub type RenderCallback = Box<dyn FnMut(&[u8], usize, usize)>;
+
 pub struct Chip8 {
     pub memory: [u8; 4096],
     pub registers: [u8; 16],        // reg V0-VF
@@ -37,13 +296,81 @@ pub struct Chip8 {
     pub sp: u8,                     // stack pointer
     pub delay_timer: u8,
     pub sound_timer: u8,
-    pub video: [u8; 64 * 32],       // 0 or 1 per pixel
-    pub keypad: [bool; 16],
-    pub opcode: u16
+    pub video: Video,               // per pixel, bit 0 = plane 1, bit 1 = plane 2; rows are screen_width() long
+    pub hires: bool,                // SCHIP 128x64 mode, see 00FE/00FF
+    pub keypad: [bool; 16],         // held keys, indexed by CHIP-8 key value 0x0-0xF
+    pub opcode: u16,
+    pub selected_planes: u8,        // bitmask of planes DRW/CLS touch, see FN01
+    pub palette: [Color; 4],
+    pub rpl_flags: [u8; 16],        // SCHIP/XO-CHIP user flags, see Fx75/Fx85
+    pub audio_pattern: AudioPattern, // XO-CHIP waveform and pitch, see F002/Fx3A
+    pub instruction_set: InstructionSet,
+    pub rom_padding: RomPadding,
+    pub quirks: QuirkProfile,
+    pub error_policy: ErrorPolicy,
+    pub status: MachineStatus,
+    pub on_halt: HaltBehavior,
+    pub cycles_per_frame: u32,      // instructions per 60Hz frame
+    pub frame_rate: f64,            // frames per second, paces run_due_frames and scales timer ticks
+    pub input_poll_interval: Option<u32>, // re-poll input every N instructions, not just once per frame
+    pub display_wait_min_instructions: u32, // with display_wait, a frame that drew still runs until this many instructions (its draw included)
+    pub machine_cycle_budget: Option<u32>, // frames end once this many machine cycles ran, instead of cycles_per_frame
+    pub cycles: u64,                // instructions executed since power-on
+    pub opcode_caps: [Option<u64>; 16], // per family (top nibble), most executions allowed before erroring
+    pub watch_vf_writes: bool,      // emit VfImplicitWrite whenever an instruction other than 6Fkk/8Fy0 writes VF
+    pub warn_on_unaligned_sprite_read: bool, // advisory, for ROMs that keep sprites word-aligned
+    pub warn_on_pc_outside_program: bool, // runaway execution check; Strict also fails the fetch
+    pub check_call_ret_pairing: bool, // advisory events for unmatched RETs and odd CALL targets
+    pub track_self_modifying_writes: bool, // log Fx33/Fx55/5xy2 writes into the ROM, see self_modifying_writes
+    pub log_stops: bool,            // print every RunOutcome to stderr as the run loop returns it
+    pub strict_decode: bool,        // treat likely-bug operands as errors (see check_operands), error_policy still decides what errors do
+    pub(crate) events: Vec<Event>,
+    pub(crate) paused: bool,        // see pause/resume, nothing runs and the timers hold still
+    pub(crate) rom_len: usize,                 // bytes of the last loaded ROM
+    pub(crate) vblank_wait: bool,   // drew this frame, further DRWs wait for tick_timers
+    pub(crate) drew: bool,          // the screen changed (DRW/CLS) during the current frame
+    pub(crate) flag_written: bool,  // set_flag ran since this was last cleared, see step_and_log
+    vf_write_noted: bool,           // the current instruction already reported its VF write
+    pub(crate) previous_keypad: [bool; 16], // keypad as it was when the current frame started
+    input: Option<Box<dyn InputSource>>,
+    sound_sink: Option<Box<dyn SoundSink>>,
+    pub(crate) pending_input: Option<[bool; 16]>, // set_frame_input, applied when the next frame starts
+    render_callback: Option<RenderCallback>,
+    post_cycle_hook: Option<PostCycleHook>,
+    pub(crate) storage: Box<dyn StorageBackend>,
+    pub(crate) history: ExecutionHistory,
+    time_source: Box<dyn TimeSource>,
+    next_frame_at: Duration,
+    clock_samples: VecDeque<(Duration, u64)>, // (time, cycles) at the end of recent frames
+    pub(crate) opcode_counts: [u64; 16], // executions per family, checked against opcode_caps
+    self_modifying_writes: Vec<(u16, u8)>,
+    pub(crate) call_returns: Vec<u16>, // return address each CALL seen by check_call_ret_pairing pushed, by depth
+    pub(crate) frame_cycle: u32,    // instructions already run in the current frame
+    pub(crate) halted_frames: u32,  // frames that ended halted since the last reset, see on_halt
+    pub(crate) frame_machine_cycles: u32, // their cost, see instruction_cost
+    pub(crate) timer_accumulator: f64, // fraction of a timer tick owed, carried between frames
+    pub(crate) breakpoints: BTreeMap<u16, Option<String>>, // with their optional labels
+    pub(crate) watchpoints: BTreeMap<u16, Option<String>>, // memory addresses, break after a write changes them
+    pub(crate) event_breakpoints: BTreeMap<EventKind, Option<String>>,
+    pub(crate) stopped_at: Option<u16>, // breakpoint we last stopped on, stepped over when resuming
+    pub(crate) rng: ChaCha12Rng,    // what StdRng is today, named so save states can record its position
+    pub(crate) rng_seed: u64,
+    initial_fill: FillPattern
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Chip8 {
     pub fn new() -> Self {
+        Self::with_seed(rand::random())
+    }
+
+    // same seed, same random numbers (and the same FillPattern::Random state) every run
+    pub fn with_seed(seed: u64) -> Self {
         let mut chip8 = Chip8 {
             memory: [0; MEMORY_SIZE as usize],
             registers: [0; 16],
@@ -53,24 +380,221 @@ impl Chip8 {
             sp: 0,
             delay_timer: 0,
             sound_timer: 0,
-            video: [0; (VIDEO_HEIGHT * VIDEO_WIDTH) as usize],
+            video: blank_video(),
+            hires: false,
             keypad: [false; 16],
-            opcode: 0
+            opcode: 0,
+            selected_planes: 1,
+            palette: DEFAULT_PALETTE,
+            rpl_flags: [0; 16],
+            audio_pattern: AudioPattern::default(),
+            instruction_set: InstructionSet::default(),
+            rom_padding: RomPadding::default(),
+            quirks: QuirkProfile::default(),
+            error_policy: ErrorPolicy::default(),
+            status: MachineStatus::default(),
+            on_halt: HaltBehavior::default(),
+            cycles_per_frame: DEFAULT_CYCLES_PER_FRAME,
+            frame_rate: DEFAULT_FRAME_RATE,
+            input_poll_interval: None,
+            display_wait_min_instructions: 0,
+            machine_cycle_budget: None,
+            cycles: 0,
+            opcode_caps: [None; 16],
+            watch_vf_writes: false,
+            warn_on_unaligned_sprite_read: false,
+            warn_on_pc_outside_program: false,
+            check_call_ret_pairing: false,
+            track_self_modifying_writes: false,
+            log_stops: false,
+            strict_decode: false,
+            events: Vec::new(),
+            paused: false,
+            rom_len: 0,
+            vblank_wait: false,
+            drew: false,
+            flag_written: false,
+            vf_write_noted: false,
+            previous_keypad: [false; 16],
+            input: None,
+            sound_sink: None,
+            pending_input: None,
+            render_callback: None,
+            post_cycle_hook: None,
+            storage: Box::new(NullStorage),
+            history: ExecutionHistory::default(),
+            time_source: Box::new(SystemTimeSource::new()),
+            next_frame_at: Duration::ZERO,
+            clock_samples: VecDeque::with_capacity(CLOCK_SAMPLE_WINDOW),
+            opcode_counts: [0; 16],
+            self_modifying_writes: Vec::new(),
+            call_returns: Vec::new(),
+            frame_cycle: 0,
+            halted_frames: 0,
+            frame_machine_cycles: 0,
+            timer_accumulator: 0.0,
+            breakpoints: BTreeMap::new(),
+            watchpoints: BTreeMap::new(),
+            event_breakpoints: BTreeMap::new(),
+            stopped_at: None,
+            rng: ChaCha12Rng::seed_from_u64(seed),
+            rng_seed: seed,
+            initial_fill: FillPattern::default()
         };
 
-        for i in 0..80 {
-            chip8.memory[FONTSET_START_ADDRESS as usize + i] = CHIP8_FONTSET[i];
-        }
+        chip8.load_fontset();
 
         chip8
     }
 
-    pub fn load_rom(&mut self, filename: &str) -> std::io::Result<()> {
+    fn load_fontset(&mut self) {
+        let font_start = FONTSET_START_ADDRESS as usize;
+        self.memory[font_start..font_start + CHIP8_FONTSET.len()].copy_from_slice(&CHIP8_FONTSET);
+        let big_font_start = BIG_FONTSET_START_ADDRESS as usize;
+        self.memory[big_font_start..big_font_start + BIG_FONTSET.len()].copy_from_slice(&BIG_FONTSET);
+    }
+
+    // the seed the RND stream currently follows (and hard_reset returns to)
+    pub fn rng_seed(&self) -> u64 {
+        self.rng_seed
+    }
+
+    // restarts RND as if the machine had been created with this seed
+    pub fn reseed_rng(&mut self, seed: u64) {
+        self.rng_seed = seed;
+        self.rng = ChaCha12Rng::seed_from_u64(seed);
+    }
+
+    // refills memory, registers and video right away, and again on every hard_reset
+    pub fn set_initial_fill(&mut self, pattern: FillPattern) {
+        self.initial_fill = pattern;
+        self.apply_initial_fill();
+    }
+
+    fn apply_initial_fill(&mut self) {
+        match self.initial_fill {
+            FillPattern::Zero => self.fill_with(0),
+            FillPattern::Byte(byte) => self.fill_with(byte),
+            FillPattern::Random => {
+                self.rng.fill(&mut self.memory[..]);
+                self.rng.fill(&mut self.registers[..]);
+                for pixel in 0..VIDEO_SIZE {
+                    self.video.set_pixel(pixel, self.rng.random_range(0..4));
+                }
+            }
+        }
+        self.load_fontset();
+    }
+
+    fn fill_with(&mut self, byte: u8) {
+        self.memory = [byte; MEMORY_SIZE as usize];
+        self.registers = [byte; 16];
+        for pixel in 0..VIDEO_SIZE {
+            self.video.set_pixel(pixel, byte);
+        }
+    }
+
+    // back to power-on: wipes memory (ROM included) and all CPU state, reseeds the RNG,
+    // but keeps configuration and whatever the storage backend holds
+    pub fn hard_reset(&mut self) {
+        self.rng = ChaCha12Rng::seed_from_u64(self.rng_seed);
+        self.apply_initial_fill();
+        self.rpl_flags = [0; 16];
+        self.cycles = 0;
+        self.opcode_counts = [0; 16];
+        self.self_modifying_writes.clear();
+        self.rom_len = 0;
+        self.clock_samples.clear();
+        self.reset_cpu();
+    }
+
+    // restart the loaded ROM: CPU state, screen and keypad go back to post-boot values and
+    // the font is reinstalled, but the rest of memory (the ROM) is left as it is
+    pub fn reset(&mut self) {
+        self.registers = [0; 16];
+        self.video = blank_video();
+        self.load_fontset();
+        self.reset_cpu();
+    }
+
+    fn reset_cpu(&mut self) {
+        self.index = 0;
+        self.pc = START_ADDRESS;
+        self.stack = [0; 16];
+        self.sp = 0;
+        self.call_returns.clear();
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.audio_pattern = AudioPattern::default();
+        self.hires = false;
+        self.keypad = [false; 16];
+        self.previous_keypad = [false; 16];
+        self.pending_input = None;
+        self.opcode = 0;
+        self.selected_planes = 1;
+        self.status = MachineStatus::Running;
+        self.halted_frames = 0;
+        self.vblank_wait = false;
+        self.drew = false;
+        self.frame_cycle = 0;
+        self.frame_machine_cycles = 0;
+        self.timer_accumulator = 0.0;
+        self.stopped_at = None;
+        self.events.clear();
+        self.history.clear();
+    }
+
+    pub fn set_storage_backend(&mut self, storage: Box<dyn StorageBackend>) {
+        self.storage = storage;
+    }
+
+    // reads the file and hands it to load_rom_from_bytes; a RomTooLarge comes back as InvalidData
+    pub fn load_rom(&mut self, filename: &str) -> std::io::Result<LoadReport> {
         let mut f = fs::File::open(filename)?;
         let mut buffer: Vec<u8> = Vec::new();
         f.read_to_end(&mut buffer)?;
 
-        for (i, &byte) in buffer.iter().enumerate() {
+        self.load_rom_from_bytes(&buffer)
+            .map_err(|error| std::io::Error::new(std::io::ErrorKind::InvalidData, error))
+    }
+
+    // copies a ROM to 0x200, e.g. one embedded with include_bytes!; a ROM that doesn't fit
+    // below the top of memory is refused with RomTooLarge before anything is written
+    pub fn load_rom_from_bytes(&mut self, data: &[u8]) -> Result<LoadReport, Chip8Error> {
+        self.check_rom_size(data.len())?;
+        Ok(self.load_program(data))
+    }
+
+    fn check_rom_size(&self, size: usize) -> Result<(), Chip8Error> {
+        let capacity = self.memory.len() - START_ADDRESS as usize;
+        if size > capacity {
+            return Err(Chip8Error::RomTooLarge { size, capacity });
+        }
+        Ok(())
+    }
+
+    // raw 4096-byte memory image, no CPU state
+    pub fn dump_memory(&self, path: &str) -> std::io::Result<()> {
+        fs::write(path, self.memory)
+    }
+
+    pub fn load_memory(&mut self, path: &str) -> std::io::Result<()> {
+        let image = fs::read(path)?;
+        if image.len() != self.memory.len() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("memory image is {} bytes, expected {}", image.len(), self.memory.len()),
+            ));
+        }
+
+        self.memory.copy_from_slice(&image);
+
+        Ok(())
+    }
+
+    // callers have already checked the size; the clamp only keeps internal loads in bounds
+    pub(crate) fn load_program(&mut self, data: &[u8]) -> LoadReport {
+        for (i, &byte) in data.iter().enumerate() {
             let addr = START_ADDRESS as usize + i;
             if addr < self.memory.len() {
                 self.memory[addr] = byte;
@@ -78,38 +602,361 @@ impl Chip8 {
                 break;
             }
         }
-        
-        Ok(())
+        self.rom_len = data.len().min(self.memory.len() - START_ADDRESS as usize);
+        self.self_modifying_writes.clear();
+
+        // first instruction slot after the ROM, so running off the end hits it
+        let pad_address: u16 = START_ADDRESS + self.rom_len.next_multiple_of(2) as u16;
+        let pad_opcode: Option<u16> = match self.rom_padding {
+            RomPadding::None => None,
+            RomPadding::Opcode(opcode) => Some(opcode),
+            RomPadding::Halt => Some(0x1000 | pad_address),
+        };
+        if let Some(opcode) = pad_opcode.filter(|_| (pad_address as usize) + 1 < self.memory.len()) {
+            self.memory[pad_address as usize] = (opcode >> 8) as u8;
+            self.memory[pad_address as usize + 1] = opcode as u8;
+        }
+
+        LoadReport {
+            start_address: START_ADDRESS,
+            bytes_loaded: self.rom_len,
+            free_bytes: self.memory.len() - START_ADDRESS as usize - self.rom_len,
+        }
+    }
+
+    pub fn emulate_cycle(&mut self) -> Result<(), Chip8Error> {
+        if self.paused || self.status == MachineStatus::Halted || self.stalled_on_vblank() {
+            return Ok(());
+        }
+
+        self.cycles += 1;
+
+        let address = self.pc;
+        let before = self.history.is_recording().then(|| Capture::take(self));
+        let result = self.execute();
+        if let Some(before) = before {
+            self.record_history(before);
+        }
+        if let Some(mut hook) = self.post_cycle_hook.take() {
+            hook(self, &DecodedInstruction::new(address, self.opcode));
+            self.post_cycle_hook = Some(hook);
+        }
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(error) => match self.error_policy {
+                ErrorPolicy::Strict => Err(error),
+                ErrorPolicy::Lenient => {
+                    self.status = MachineStatus::Halted;
+                    Ok(())
+                }
+            },
+        }
+    }
+
+    // one 60Hz frame: a batch of instructions followed by a single timer tick
+    //
+    // With the display_wait quirk the frame ends early once it has drawn: right away by
+    // default, or once the frame has run display_wait_min_instructions counting from its
+    // start, so draw-every-frame games keep some throughput. A second DRW in the same frame
+    // always waits for the next one.
+    // A breakpoint stops the frame part way, the next call picks it up from there.
+    pub fn run_frame(&mut self) -> RunOutcome {
+        let outcome = self.run_loop(None, true, &|_| false);
+
+        if outcome.reason == StopReason::Halted {
+            self.halted_frames += 1;
+            let restart = match self.on_halt {
+                HaltBehavior::Stop => false,
+                HaltBehavior::Restart => true,
+                HaltBehavior::RestartAfterFrames(frames) => self.halted_frames >= frames,
+            };
+            if restart {
+                self.reset();
+            }
+        }
+
+        outcome
+    }
+
+    // one instruction of the current frame, opening and closing frames as needed;
+    // Ok(true) when this step ended the frame (timers ticked)
+    pub(crate) fn step_in_frame(&mut self) -> Result<bool, Chip8Error> {
+        if self.frame_cycle == 0 {
+            let before = self.history.is_recording().then(|| Capture::take(self));
+            self.drew = false;
+            self.previous_keypad = self.keypad;
+            self.poll_input();
+            if let Some(keys) = self.pending_input.take() {
+                self.keypad = keys;
+            }
+            if let Some(before) = before {
+                self.record_frame_end(before);
+            }
+        } else if let Some(interval) = self.input_poll_interval.filter(|&interval| interval > 0)
+            && self.frame_cycle.is_multiple_of(interval) {
+            self.poll_input();
+        }
+
+        let waiting_for_vblank = self.vblank_wait
+            && (self.frame_cycle >= self.display_wait_min_instructions || self.stalled_on_vblank());
+        if !waiting_for_vblank && !self.frame_full() {
+            let cost = self.instruction_cost(self.fetch_opcode(self.pc));
+            let (old_cycle, old_machine_cycles) = (self.frame_cycle, self.frame_machine_cycles);
+            self.emulate_cycle()?;
+            self.frame_cycle += 1;
+            self.frame_machine_cycles += cost;
+            if self.history.is_recording() {
+                self.record_frame_count(old_cycle, old_machine_cycles);
+            }
+        }
+
+        if waiting_for_vblank || self.frame_full() {
+            let before = self.history.is_recording().then(|| Capture::take(self));
+            self.frame_cycle = 0;
+            self.frame_machine_cycles = 0;
+            self.advance_timers();
+            if let Some(before) = before {
+                self.record_frame_end(before);
+            }
+            self.record_clock_sample();
+            if self.drew && self.render_callback.is_some() {
+                let (width, height) = (self.screen_width(), self.screen_height());
+                let pixels = self.video_pixels();
+                if let Some(callback) = self.render_callback.as_mut() {
+                    callback(&pixels, width, height);
+                }
+            }
+            return Ok(true);
+        }
+
+        Ok(false)
+    }
+
+    fn frame_full(&self) -> bool {
+        match self.machine_cycle_budget {
+            Some(budget) => self.frame_machine_cycles >= budget,
+            None => self.frame_cycle >= self.cycles_per_frame,
+        }
+    }
+
+    // machine cycles an opcode takes against machine_cycle_budget; DRW grows with the
+    // sprite height, and with display_wait it also ends the frame like the VIP's vblank wait.
+    // Fx55/Fx65 grow with the number of registers moved.
+    pub fn instruction_cost(&self, opcode: u16) -> u32 {
+        match opcode & 0xF000 {
+            0xD000 => DRW_BASE_COST + DRW_ROW_COST * (opcode & 0x000F) as u32,
+            0xF000 if matches!(opcode & 0x00FF, 0x55 | 0x65) => {
+                LOAD_STORE_BASE_COST + LOAD_STORE_REGISTER_COST * (((opcode & 0x0F00) >> 8) as u32 + 1)
+            }
+            _ => BASE_INSTRUCTION_COST,
+        }
+    }
+
+    fn record_clock_sample(&mut self) {
+        if self.clock_samples.len() == CLOCK_SAMPLE_WINDOW {
+            self.clock_samples.pop_front();
+        }
+        self.clock_samples.push_back((self.time_source.now(), self.cycles));
+    }
+
+    // instructions per second actually achieved over the last few frames, 0 until measurable
+    pub fn measured_hz(&self) -> f64 {
+        let (Some(&(start, first)), Some(&(end, last))) = (self.clock_samples.front(), self.clock_samples.back()) else {
+            return 0.0;
+        };
+
+        let elapsed = (end - start).as_secs_f64();
+        if elapsed <= 0.0 {
+            return 0.0;
+        }
+
+        (last - first) as f64 / elapsed
+    }
+
+    // general instrumentation point: sees the machine right after each instruction ran
+    pub fn set_post_cycle_hook(&mut self, hook: PostCycleHook) {
+        self.post_cycle_hook = Some(hook);
+    }
+
+    // called with (video, width, height) at the end of every frame that drew something
+    pub fn set_render_callback(&mut self, callback: RenderCallback) {
+        self.render_callback = Some(callback);
+    }
+
+    pub fn set_input_source(&mut self, input: Box<dyn InputSource>) {
+        self.input = Some(input);
+    }
+
+    pub fn set_sound_sink(&mut self, sink: Box<dyn SoundSink>) {
+        self.sound_sink = Some(sink);
+    }
+
+    // simpler form of set_sound_sink that replaces it: `callback` gets true when the beep starts
+    // and false when it stops, from tick_timers or Fx18. A beep already running counts as started.
+    pub fn on_sound(&mut self, callback: Box<dyn FnMut(bool)>) {
+        let mut sink = SoundCallback::new(callback);
+        if self.is_beeping() {
+            sink.start(&self.audio_pattern);
+        }
+        self.sound_sink = Some(Box::new(sink));
+    }
+
+    pub fn is_beeping(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    // (key, is_down) for every key whose state differs from the start of the current frame
+    pub fn keypad_changes_since_last_frame(&self) -> Vec<(u8, bool)> {
+        self.keypad
+            .iter()
+            .zip(self.previous_keypad.iter())
+            .enumerate()
+            .filter(|(_, (now, before))| now != before)
+            .map(|(key, (&now, _))| (key as u8, now))
+            .collect()
+    }
+
+    fn poll_input(&mut self) {
+        if let Some(input) = self.input.as_mut() {
+            self.keypad = input.poll();
+        }
+    }
+
+    pub fn set_time_source(&mut self, time_source: Box<dyn TimeSource>) {
+        self.next_frame_at = time_source.now();
+        self.time_source = time_source;
+    }
+
+    // runs however many frames are due according to the time source, returns how many ran
+    pub fn run_due_frames(&mut self) -> Result<u32, Chip8Error> {
+        let now = self.time_source.now();
+        let frame_duration = Duration::from_secs_f64(1.0 / self.frame_rate);
+        let mut frames = 0;
+
+        if self.paused {
+            // don't owe the game a burst of frames for the time it spent paused
+            self.next_frame_at = now + frame_duration;
+            return Ok(0);
+        }
+
+        while self.next_frame_at <= now {
+            if frames == MAX_CATCH_UP_FRAMES {
+                self.next_frame_at = now + frame_duration;
+                break;
+            }
+
+            let outcome = self.run_frame();
+            self.next_frame_at += frame_duration;
+            frames += 1;
+
+            match outcome.reason {
+                StopReason::Error(error) => return Err(error),
+                reason if reason.is_break() => {
+                    // hand control to the debugger instead of catching up behind its back
+                    self.next_frame_at = now + frame_duration;
+                    break;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(frames)
+    }
+
+    pub(crate) fn fetch_opcode(&self, address: u16) -> u16 {
+        let address = address as usize;
+
+        ((self.memory[address % MEMORY_SIZE as usize] as u16) << 8) | (self.memory[(address + 1) % MEMORY_SIZE as usize] as u16)
+    }
+
+    // the address I actually reads from, as sprite and load/store accesses wrap at the top of memory
+    pub fn index_effective(&self) -> u16 {
+        (self.index as usize % self.memory.len()) as u16
+    }
+
+    // how many times opcodes with this top nibble have run since power-on
+    pub fn opcode_count(&self, family: u8) -> u64 {
+        self.opcode_counts[family as usize & 0xF]
+    }
+
+    // [START_ADDRESS, START_ADDRESS + rom_len) as of the last load
+    fn pc_in_program(&self, pc: u16) -> bool {
+        (START_ADDRESS as usize..START_ADDRESS as usize + self.rom_len).contains(&(pc as usize))
+    }
+
+    // the next instruction is a DRW that has to wait for the next frame
+    fn stalled_on_vblank(&self) -> bool {
+        self.vblank_wait && self.fetch_opcode(self.pc) & 0xF000 == 0xD000
     }
 
-    pub fn emulate_cycle(&mut self) {
-        self.opcode = ((self.memory[self.pc as usize] as u16) << 8) | (self.memory[(self.pc + 1) as usize] as u16);
+    fn execute(&mut self) -> Result<(), Chip8Error> {
+        if self.warn_on_pc_outside_program && !self.pc_in_program(self.pc) {
+            self.events.push(Event::PcOutsideProgram { pc: self.pc });
+            if self.error_policy == ErrorPolicy::Strict {
+                return Err(Chip8Error::PcOutsideProgram(self.pc));
+            }
+        }
+
+        self.opcode = self.fetch_opcode(self.pc);
+        self.vf_write_noted = false;
 
-        let nnn: u16 = self.opcode & 0x0FFF;
-        let kk: u8 = (self.opcode & 0x00FF) as u8;
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        let y: usize = ((self.opcode & 0x00F0) >> 4) as usize;
-        let n: u8 = (self.opcode & 0x000F) as u8;
+        // before advancing, so a capped instruction leaves PC on itself
+        let family = (self.opcode >> 12) as usize;
+        if let Some(cap) = self.opcode_caps[family] && self.opcode_counts[family] >= cap {
+            return Err(Chip8Error::OpcodeCapExceeded { family: family as u8, cap });
+        }
+        self.opcode_counts[family] += 1;
+
+        self.pc += 2;                   // advance before executing so jumps/skips work off the next instruction
 
-        // eventually imma have to match on opcodes to execute instructions
-        println!("Fetched opcode: {:#X}, nnn={:#X}, kk={:#X}, x={}, y={}, n={}", self.opcode, nnn, kk, x, y, n);
         match self.opcode & 0xF000 {
-            0x0000 => match self.opcode & 0x00FF { 
+            0x0000 => match self.opcode {
                 0x00E0 => self.cls(),
-                0x00EE => self.ret(),
-                _ => eprintln!("Unknown 0x0NNN opcode: {:#X}", self.opcode),
+                0x00EE => self.ret()?,
+                0x00FB if self.instruction_set >= InstructionSet::SuperChip => self.scroll(4, 0), /* SCR */
+                0x00FC if self.instruction_set >= InstructionSet::SuperChip => self.scroll(-4, 0), /* SCL */
+                0x00FD if self.instruction_set >= InstructionSet::SuperChip => self.status = MachineStatus::Halted, /* EXIT */
+                0x00FE if self.instruction_set >= InstructionSet::SuperChip => self.set_hires(false), /* LOW */
+                0x00FF if self.instruction_set >= InstructionSet::SuperChip => self.set_hires(true), /* HIGH */
+                _ if self.opcode & 0xFFF0 == 0x00C0 && self.instruction_set >= InstructionSet::SuperChip => { /* SCD n */
+                    let n: u8 = (self.opcode & 0x000F) as u8;
+
+                    self.scroll(0, n as isize);
+                }
+                _ if self.opcode & 0xFFF0 == 0x00D0 && self.instruction_set >= InstructionSet::XoChip => { /* SCU n */
+                    let n: u8 = (self.opcode & 0x000F) as u8;
+
+                    self.scroll(0, -(n as isize));
+                }
+                // SYS addr called native 1802 code on the VIP; nothing to run here, so it is
+                // a no-op unless the error policy wants to hear about it
+                _ if self.error_policy == ErrorPolicy::Strict => self.unknown_opcode()?,
+                _ => {}
             },
             0x1000 => { // JMP addr
                 let address: u16 = self.opcode & 0x0FFF;
+                self.check_jump_target(address)?;
+                if address == self.pc - 2 {
+                    self.status = MachineStatus::Halted; // jumping to itself, nothing else can ever run
+                }
                 self.pc = address;
 
             },
             0x2000 => { // CALL addr
                 let address: u16 = self.opcode & 0x0FFF;
+                self.check_jump_target(address)?;
+                if self.check_call_ret_pairing && address % 2 == 1 {
+                    self.events.push(Event::MisalignedCall { pc: self.pc - 2, target: address });
+                }
                 if self.sp as usize >= self.stack.len() {
-                    panic!("Stack overflow");
+                    return Err(Chip8Error::StackOverflow { attempted_depth: self.sp as usize + 1 });
+                }
+                self.stack[self.sp as usize] = self.pc; // to save return address cuz CALL needs to save
+                if self.check_call_ret_pairing {
+                    self.call_returns.truncate(self.sp as usize);
+                    self.call_returns.push(self.pc);
                 }
-                self.stack[self.sp as usize] = self.pc + 2; // to save return address cuz CALL needs to save
                 self.sp += 1;
                 self.pc = address;
             },
@@ -129,144 +976,1982 @@ impl Chip8 {
                     self.pc += 2;
                 }
             },
-            0x5000 => { /* SE Vx, Vy */
-                let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
-                let vy: u8 = ((self.opcode & 0x00F0) >> 4) as u8;
+            0x5000 => match self.opcode & 0x000F {
+                0x0000 => { /* SE Vx, Vy */
+                    let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+                    let vy: u8 = ((self.opcode & 0x00F0) >> 4) as u8;
 
-                if self.registers[vx as usize] == self.registers[vy as usize] {
-                    self.pc += 2;
+                    if self.registers[vx as usize] == self.registers[vy as usize] {
+                        self.pc += 2;
+                    }
+                }
+                0x0002 if self.instruction_set >= InstructionSet::XoChip => { /* SAVE Vx - Vy */
+                    let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+                    let vy: u8 = ((self.opcode & 0x00F0) >> 4) as u8;
+
+                    self.check_memory_range(vx.abs_diff(vy) as u16 + 1)?;
+                    for (offset, reg) in register_range(vx, vy).enumerate() {
+                        self.write_memory(self.index as usize + offset, self.registers[reg]);
+                    }
+                }
+                0x0003 if self.instruction_set >= InstructionSet::XoChip => { /* LOAD Vx - Vy */
+                    let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+                    let vy: u8 = ((self.opcode & 0x00F0) >> 4) as u8;
+
+                    self.check_memory_range(vx.abs_diff(vy) as u16 + 1)?;
+                    for (offset, reg) in register_range(vx, vy).enumerate() {
+                        self.set_register(reg, self.memory[(self.index as usize + offset) % MEMORY_SIZE as usize]);
+                    }
                 }
+                _ => self.unknown_opcode()?,
             },
             0x6000 => { /* LD Vx, byte */
                 let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
                 let byte: u8 = (self.opcode & 0x00FF) as u8;
 
-                self.registers[vx as usize] = byte;
+                self.set_register(vx as usize, byte);
             },
             0x7000 => { /* ADD Vx, byte */
                 let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
                 let byte: u8 = (self.opcode & 0x00FF) as u8;
 
-                self.registers[vx as usize] = self.registers[vx as usize].wrapping_add(byte);
+                self.set_register(vx as usize, self.registers[vx as usize].wrapping_add(byte));
             },
             0x8000 => match self.opcode & 0x000F {
                 0x0000 => { /* LD Vx, Vy */
                     let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
                     let vy: u8 = ((self.opcode & 0x00F0) >> 4) as u8;
 
-                    self.registers[vx as usize] = self.registers[vy as usize];
+                    self.set_register(vx as usize, self.registers[vy as usize]);
                 }
                 0x0001 => { /* OR Vx, Vy */
                     let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
                     let vy: u8 = ((self.opcode & 0x00F0) >> 4) as u8;
 
-                    self.registers[vx as usize] != self.registers[vy as usize];
+                    self.set_register(vx as usize, self.registers[vx as usize] | self.registers[vy as usize]);
                 }
                 0x0002 => { /* AND Vx, Vy */
                     let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
                     let vy: u8 = ((self.opcode & 0x00F0) >> 4) as u8;
 
-                    self.registers[vx as usize] &= self.registers[vy as usize];
+                    self.set_register(vx as usize, self.registers[vx as usize] & self.registers[vy as usize]);
                 }
                 0x0003 => { /* XOR Vx, Vy */
                     let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
                     let vy: u8 = ((self.opcode & 0x00F0) >> 4) as u8;
 
-                    self.registers[vx as usize] ^= self.registers[vy as usize];
+                    self.set_register(vx as usize, self.registers[vx as usize] ^ self.registers[vy as usize]);
                 }
+                // 8xy4-8xyE read both operands before writing anything and write the
+                // flag last, so VF ends up holding the flag even when x or y is F; the
+                // result goes straight into the register since set_flag reports VF anyway
                 0x0004 => { /* ADD Vx, Vy */
                     let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
                     let vy: u8 = ((self.opcode & 0x00F0) >> 4) as u8;
 
-                    let sum: u16 = (self.registers[vx as usize] + self.registers[vy as usize]) as u16;
-
-                    if sum > 255 {
-                        self.registers[0xF] = 1;
-                    } else {
-                        self.registers[0xF] = 0;
-                    }
+                    let (result, carry) = self.registers[vx as usize].overflowing_add(self.registers[vy as usize]);
 
-                    self.registers[vx as usize] = (sum & 0xFF) as u8;
+                    self.registers[vx as usize] = result;
+                    self.set_flag(carry as u8);
                 }
                 0x0005 => { /* SUB Vx, Vy */
                     let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
                     let vy: u8 = ((self.opcode & 0x00F0) >> 4) as u8;
 
-                    if self.registers[vx as usize] > self.registers[vy as usize] {
-                        self.registers[0xF] = 1;
-                    } else {
-                        self.registers[0xF] = 0;
-                    }
+                    let (result, borrow) = self.registers[vx as usize].overflowing_sub(self.registers[vy as usize]);
 
-                    self.registers[vx as usize] -= self.registers[vy as usize];
+                    self.registers[vx as usize] = result;
+                    self.set_flag(!borrow as u8);
                 }
-                0x0006 => { /* SHR Vx */
+                0x0006 => { /* SHR Vx {, Vy} */
                     let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+                    let vy: u8 = ((self.opcode & 0x00F0) >> 4) as u8;
+
+                    let value: u8 = self.registers[if self.quirks.shift_vy { vy } else { vx } as usize];
 
-                    self.registers[0xF] = self.registers[vx as usize] & 0x1;
-                    self.registers[vx as usize] >>= 1;
+                    self.registers[vx as usize] = value >> 1;
+                    self.set_flag(value & 0x1);
                 }
                 0x0007 => { /* SUBN Vx, Vy */
                     let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
                     let vy: u8 = ((self.opcode & 0x00F0) >> 4) as u8;
 
-                    if self.registers[vy as usize] > self.registers[vx as usize] {
-                        self.registers[0xF] = 1;
-                    } else {
-                        self.registers[0xF] = 0;
-                    }
+                    let (result, borrow) = self.registers[vy as usize].overflowing_sub(self.registers[vx as usize]);
 
-                    self.registers[vx as usize] = self.registers[vy as usize] - self.registers[vx as usize];
+                    self.registers[vx as usize] = result;
+                    self.set_flag(!borrow as u8);
                 }
-                0x000E => { /* SHL Vx */
+                0x000E => { /* SHL Vx {, Vy} */
                     let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+                    let vy: u8 = ((self.opcode & 0x00F0) >> 4) as u8;
 
-                    self.registers[0xF] = (self.registers[vx as usize] & 0x80) >> 7;
-                    self.registers[vx as usize] <<= 1;
+                    let value: u8 = self.registers[if self.quirks.shift_vy { vy } else { vx } as usize];
+
+                    self.registers[vx as usize] = value << 1;
+                    self.set_flag((value & 0x80) >> 7);
                 }
-                _ => eprintln!("Unknown opcode: {:04X}", self.opcode)
-            },
-            0x9000 => { /* SNE Vx, Vy */ }
-            0xA000 => { /* LD I, addr */ }
-            0xB000 => { /* JP V0, addr */ }
-            0xC000 => { /* RND Vx, byte */ }
-            0xD000 => { /* DRW Vx, Vy, nibble */ }
-            0xE000 => match self.opcode & 0x00FF {
-                0x009E => { /* SKP Vx */ }
-                0x00A1 => { /* SKNP Vx */ }
-                _ => eprintln!("Unknown opcode: {:04X}", self.opcode),
+                _ => self.unknown_opcode()?,
             },
-            0xF000 => match self.opcode & 0x00FF {
-                0x0007 => { /* LD Vx, DT */ }
-                0x000A => { /* LD Vx, K */ }
-                0x0015 => { /* LD DT, Vx */ }
-                0x0018 => { /* LD ST, Vx */ }
-                0x001E => { /* ADD I, Vx */ }
-                0x0029 => { /* LD F, Vx */ }
-                0x0033 => { /* LD B, Vx */ }
-                0x0055 => { /* LD [I], V0..Vx */ }
-                0x0065 => { /* LD V0..Vx, [I] */ }
-                _ => eprintln!("Unknown opcode: {:04X}", self.opcode),
+            0x9000 => match self.opcode & 0x000F {
+                0x0000 => { /* SNE Vx, Vy */
+                    let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+                    let vy: u8 = ((self.opcode & 0x00F0) >> 4) as u8;
+
+                    if self.registers[vx as usize] != self.registers[vy as usize] {
+                        self.pc += 2;
+                    }
+                }
+                _ => self.unknown_opcode()?,
             },
-            _ => eprintln!("Unknown opcode: {:04X}", self.opcode),
-        }
+            0xA000 => { /* LD I, addr */
+                let address: u16 = self.opcode & 0x0FFF;
 
-        self.pc += 2;
+                self.index = address;
+            }
+            0xB000 => { /* JP V0, addr */
+                let address: u16 = self.opcode & 0x0FFF;
+                let reg: usize = if self.quirks.jump_vx { (address >> 8) as usize } else { 0 };
+                let target: u16 = address + self.registers[reg] as u16;
 
-    }
+                self.check_jump_target(target)?;
+                self.pc = target & 0x0FFF;
+            }
+            0xC000 => { /* RND Vx, byte */
+                let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+                let kk: u8 = (self.opcode & 0x00FF) as u8;
 
-    fn cls(&mut self) {
-        for pixel in self.video.iter_mut() {
-            *pixel = 0;
-        }
-    }
+                let value = self.rng.random::<u8>() & kk;
+                self.set_register(vx as usize, value);
+            }
+            0xD000 => { /* DRW Vx, Vy, nibble */
+                let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+                let vy: u8 = ((self.opcode & 0x00F0) >> 4) as u8;
+                let height: u8 = (self.opcode & 0x000F) as u8;
 
-    fn ret(&mut self) {
-        if self.sp > 0 {
-            self.sp -= 1;
-            self.pc = self.stack[self.sp as usize];
-        } else {
-            panic!("Stackoverflow on RET")
-        }
+                self.drw(vx as usize, vy as usize, height)?;
+                self.vblank_wait = self.quirks.display_wait;
+            }
+            0xE000 => match self.opcode & 0x00FF {
+                0x009E => { /* SKP Vx */
+                    let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+
+                    if self.is_key_down(self.registers[vx as usize] & 0xF) {
+                        self.pc += 2;
+                    }
+                }
+                0x00A1 => { /* SKNP Vx */
+                    let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+
+                    if !self.is_key_down(self.registers[vx as usize] & 0xF) {
+                        self.pc += 2;
+                    }
+                }
+                _ => self.unknown_opcode()?,
+            },
+            0xF000 => match self.opcode & 0x00FF {
+                0x0001 if self.instruction_set >= InstructionSet::XoChip => { /* PLANE n */
+                    let planes: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+
+                    self.selected_planes = planes & 0x3;
+                }
+                0x0002 if self.opcode == 0xF002 && self.instruction_set >= InstructionSet::XoChip => { /* AUDIO */
+                    let mut bits = [0u8; 16];
+                    for (offset, byte) in bits.iter_mut().enumerate() {
+                        *byte = self.memory[(self.index as usize + offset) % MEMORY_SIZE as usize];
+                    }
+
+                    self.set_audio_pattern(AudioPattern { bits, ..self.audio_pattern });
+                }
+                0x0007 => { /* LD Vx, DT */
+                    let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+
+                    self.set_register(vx as usize, self.delay_timer);
+                }
+                0x000A => { /* LD Vx, K */
+                    let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+
+                    // lowest key held down, otherwise run this instruction again next cycle; the
+                    // status says we're waiting so it survives pause and save states explicitly
+                    match (0..16u8).find(|&key| self.is_key_down(key)) {
+                        Some(key) => {
+                            self.set_register(vx as usize, key);
+                            self.status = MachineStatus::Running;
+                        }
+                        None => {
+                            self.status = MachineStatus::WaitingForKey(vx);
+                            self.pc -= 2;
+                        }
+                    }
+                }
+                0x0015 => { /* LD DT, Vx */
+                    let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+
+                    self.set_delay_timer(self.registers[vx as usize]);
+                }
+                0x0018 => { /* LD ST, Vx */
+                    let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+
+                    self.set_sound_timer(self.registers[vx as usize]);
+                }
+                0x001E => { /* ADD I, Vx */
+                    let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+
+                    self.index = self.index.wrapping_add(self.registers[vx as usize] as u16);
+                    if self.quirks.add_i_overflow_flag {
+                        self.set_flag((self.index > 0x0FFF) as u8);
+                    }
+                }
+                0x0029 => { /* LD F, Vx */
+                    let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+                    let digit: u8 = self.registers[vx as usize];
+
+                    // only the low nibble names a glyph; anything above is most likely a ROM bug
+                    if digit > 0xF && self.check_operands() {
+                        return Err(Chip8Error::InvalidFontDigit(digit));
+                    }
+                    self.index = FONTSET_START_ADDRESS + (digit & 0xF) as u16 * 5;
+                }
+                0x0030 if self.instruction_set >= InstructionSet::SuperChip => { /* LD HF, Vx */
+                    let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+                    let digit: u8 = self.registers[vx as usize];
+
+                    if digit > 0xF && self.check_operands() {
+                        return Err(Chip8Error::InvalidFontDigit(digit));
+                    }
+                    self.index = BIG_FONTSET_START_ADDRESS + (digit & 0xF) as u16 * 10;
+                }
+                0x0033 => { /* LD B, Vx */
+                    let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+
+                    let value: u8 = self.registers[vx as usize];
+                    self.check_memory_range(3)?;
+                    self.write_memory(self.index as usize, value / 100);
+                    self.write_memory(self.index as usize + 1, value / 10 % 10);
+                    self.write_memory(self.index as usize + 2, value % 10);
+                }
+                0x0055 => { /* LD [I], V0..Vx */
+                    let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+
+                    self.check_memory_range(vx as u16 + 1)?;
+                    for reg in 0..=vx as usize {
+                        self.write_memory(self.index as usize + reg, self.registers[reg]);
+                    }
+                    self.finish_load_store(vx);
+                }
+                0x0065 => { /* LD V0..Vx, [I] */
+                    let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+
+                    self.check_memory_range(vx as u16 + 1)?;
+                    for reg in 0..=vx as usize {
+                        self.set_register(reg, self.memory[(self.index as usize + reg) % MEMORY_SIZE as usize]);
+                    }
+                    self.finish_load_store(vx);
+                }
+                0x003A if self.instruction_set >= InstructionSet::XoChip => { /* PITCH Vx */
+                    let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+
+                    self.set_audio_pattern(AudioPattern { pitch: self.registers[vx as usize], ..self.audio_pattern });
+                }
+                0x0075 if self.instruction_set >= InstructionSet::SuperChip => { /* LD R, Vx */
+                    let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+                    let count = vx as usize + 1;
+
+                    self.check_flag_register(vx)?;
+                    self.rpl_flags[..count].copy_from_slice(&self.registers[..count]);
+                    if self.storage.store(RPL_FLAGS_KEY, &self.rpl_flags).is_err() {
+                        self.events.push(Event::StorageWriteFailed { pc: self.pc - 2 });
+                    }
+                }
+                0x0085 if self.instruction_set >= InstructionSet::SuperChip => { /* LD Vx, R */
+                    let vx: u8 = ((self.opcode & 0x0F00) >> 8) as u8;
+                    let count = vx as usize + 1;
+
+                    self.check_flag_register(vx)?;
+                    if let Some(stored) = self.storage.load(RPL_FLAGS_KEY) {
+                        let len = stored.len().min(self.rpl_flags.len());
+                        self.rpl_flags[..len].copy_from_slice(&stored[..len]);
+                    }
+                    for reg in 0..count {
+                        self.set_register(reg, self.rpl_flags[reg]);
+                    }
+                }
+                _ => self.unknown_opcode()?,
+            },
+            _ => self.unknown_opcode()?,
+        }
+
+        Ok(())
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        let was_beeping = self.sound_timer > 0;
+        self.sound_timer = value;
+
+        if !was_beeping && value > 0 {
+            self.events.push(Event::SoundStart);
+            if let Some(sink) = self.sound_sink.as_mut() {
+                sink.start(&self.audio_pattern);
+            }
+        } else if was_beeping && value == 0 {
+            self.events.push(Event::SoundStop);
+            if let Some(sink) = self.sound_sink.as_mut() {
+                sink.stop();
+            }
+        }
+    }
+
+    // a sound that is already playing picks up the new pattern or pitch straight away
+    fn set_audio_pattern(&mut self, pattern: AudioPattern) {
+        self.audio_pattern = pattern;
+        if self.sound_timer > 0 && let Some(sink) = self.sound_sink.as_mut() {
+            sink.start(&self.audio_pattern);
+        }
+    }
+
+    // called at 60Hz, separately from the instruction clock
+    pub fn tick_timers(&mut self) {
+        self.vblank_wait = false;
+
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+
+        if self.sound_timer > 0 {
+            self.set_sound_timer(self.sound_timer - 1);
+        }
+    }
+
+    // end of a frame: ticks the timers once per 1/60s the frame stood for, carrying the
+    // fraction over, so e.g. 59.94 frames still count down 60 times a second without drift
+    fn advance_timers(&mut self) {
+        self.vblank_wait = false;
+        self.timer_accumulator += TIMER_RATE / self.frame_rate;
+
+        // the epsilon keeps e.g. five frames of 1.2 from summing to 5.9999... and losing a tick
+        let ticks = (self.timer_accumulator + 1e-9).floor();
+        self.timer_accumulator = (self.timer_accumulator - ticks).max(0.0);
+        for _ in 0..ticks as u32 {
+            self.tick_timers();
+        }
+    }
+
+    // drains everything that happened since the last call
+    pub fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    pub fn set_palette(&mut self, palette: [Color; 4]) {
+        self.palette = palette;
+    }
+
+    // palette index (0-3) of every pixel on screen, row-major
+    pub fn video_pixels(&self) -> Vec<u8> {
+        self.active_pixels().collect()
+    }
+
+    // lit pixels on screen; a pixel lit in both XO-CHIP planes counts twice
+    pub fn lit_pixel_count(&self) -> u32 {
+        self.active_pixels().map(|pixel| pixel.count_ones()).sum()
+    }
+
+    pub fn screen_width(&self) -> usize {
+        if self.hires { HIRES_WIDTH } else { LORES_WIDTH }
+    }
+
+    pub fn screen_height(&self) -> usize {
+        if self.hires { HIRES_HEIGHT } else { LORES_HEIGHT }
+    }
+
+    // the part of the video buffer the current resolution uses, row-major
+    pub(crate) fn active_pixels(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.screen_width() * self.screen_height()).map(|pixel| self.video.get_pixel(pixel))
+    }
+
+    // instruction writes to memory (Fx33/Fx55/5xy2), wrapping at the top and logged when they land in the ROM
+    fn write_memory(&mut self, address: usize, value: u8) {
+        let address = (address % self.memory.len()) as u16;
+        self.memory[address as usize] = value;
+
+        if self.track_self_modifying_writes && self.pc_in_program(address) {
+            self.self_modifying_writes.push((address, value));
+        }
+    }
+
+    // (address, value) of every write into the loaded ROM since it was loaded
+    pub fn self_modifying_writes(&self) -> Vec<(u16, u8)> {
+        self.self_modifying_writes.clone()
+    }
+
+    // quirk-dependent tail of Fx55/Fx65; VF is otherwise only touched by Fx65 loading into it
+    fn finish_load_store(&mut self, vx: u8) {
+        let step: u16 = match self.quirks.load_store_increment {
+            IndexIncrement::XPlusOne => vx as u16 + 1,
+            IndexIncrement::X => vx as u16,
+            IndexIncrement::None => 0,
+        };
+        self.index = self.index.wrapping_add(step);
+
+        if self.quirks.load_store_clears_vf {
+            self.set_flag(0);
+        }
+    }
+
+    // strict_decode (or ErrorPolicy::Strict) turns operands that are valid but almost certainly
+    // a ROM bug into errors: jumps off the end of memory, Fx29/Fx30 digits above 0xF, sprites and
+    // Fx33/Fx55/Fx65/5xy2/5xy3 runs past the top of memory, and SCHIP flag registers above V7
+    fn check_operands(&self) -> bool {
+        self.strict_decode || self.error_policy == ErrorPolicy::Strict
+    }
+
+    // the two opcode bytes at `target` have to be inside memory
+    fn check_jump_target(&self, target: u16) -> Result<(), Chip8Error> {
+        if target as usize + 1 >= MEMORY_SIZE as usize && self.check_operands() {
+            return Err(Chip8Error::JumpOutOfRange(target));
+        }
+        Ok(())
+    }
+
+    // Fx33/Fx55/Fx65 and 5xy2/5xy3 touch `len` bytes from I
+    fn check_memory_range(&self, len: u16) -> Result<(), Chip8Error> {
+        if self.index as usize + len as usize > MEMORY_SIZE as usize && self.check_operands() {
+            return Err(Chip8Error::MemoryOutOfBounds { index: self.index, len });
+        }
+        Ok(())
+    }
+
+    // SCHIP has 8 flag registers, XO-CHIP all 16
+    fn check_flag_register(&self, vx: u8) -> Result<(), Chip8Error> {
+        if vx > 7 && self.instruction_set == InstructionSet::SuperChip && self.check_operands() {
+            return Err(Chip8Error::InvalidFlagRegister(vx));
+        }
+        Ok(())
+    }
+
+    // strict machines refuse opcodes outside the instruction set, lenient ones skip them
+    fn unknown_opcode(&mut self) -> Result<(), Chip8Error> {
+        match self.error_policy {
+            ErrorPolicy::Strict => Err(Chip8Error::InvalidOpcode(self.opcode)),
+            ErrorPolicy::Lenient => {
+                eprintln!("Unknown opcode: {:04X}", self.opcode);
+                Ok(())
+            }
+        }
+    }
+
+    // carry/borrow/shift-out/collision results, as opposed to an explicit LD into VF
+    fn set_flag(&mut self, value: u8) {
+        self.registers[0xF] = value;
+        self.flag_written = true;
+        self.note_vf_write();
+    }
+
+    // every other register write an instruction makes, so watch_vf_writes sees them all
+    fn set_register(&mut self, reg: usize, value: u8) {
+        self.registers[reg] = value;
+        if reg == 0xF {
+            self.note_vf_write();
+        }
+    }
+
+    // LD VF, byte and LD VF, Vy are the only writes meant as plain loads into VF;
+    // anything else reports once per instruction
+    fn note_vf_write(&mut self) {
+        let explicit_load = self.opcode & 0xF000 == 0x6000 || self.opcode & 0xF00F == 0x8000;
+        if self.watch_vf_writes && !explicit_load && !self.vf_write_noted {
+            self.vf_write_noted = true;
+            self.events.push(Event::VfImplicitWrite { pc: self.pc.wrapping_sub(2), opcode: self.opcode });
+        }
+    }
+
+    fn cls(&mut self) {
+        self.drew = true;
+        for pixel in 0..VIDEO_SIZE {
+            let value = self.video.get_pixel(pixel);
+            self.video.set_pixel(pixel, value & !self.selected_planes);
+        }
+    }
+
+    // 00FE/00FF; the screen is cleared whenever the resolution changes
+    pub(crate) fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.video = blank_video();
+        self.drew = true;
+    }
+
+    // moves the selected planes by (dx, dy) pixels in the current resolution; whatever is
+    // scrolled in is blank. Never touches VF.
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let width = self.screen_width() as isize;
+        let height = self.screen_height() as isize;
+        let planes = self.selected_planes;
+        let before = self.video;
+
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - dx, y - dy);
+                let scrolled_in = if (0..width).contains(&src_x) && (0..height).contains(&src_y) {
+                    before.get_pixel((src_y * width + src_x) as usize) & planes
+                } else {
+                    0
+                };
+                let pixel = (y * width + x) as usize;
+                let kept = self.video.get_pixel(pixel) & !planes;
+                self.video.set_pixel(pixel, kept | scrolled_in);
+            }
+        }
+
+        self.drew = true;
+    }
+
+    // Dxy0 is a 16x16 sprite (two bytes a row) from SCHIP on, in either resolution.
+    // Sprite data running past the top of memory wraps to 0x000, or is an error with check_operands.
+    fn drw(&mut self, vx: usize, vy: usize, height: u8) -> Result<(), Chip8Error> {
+        let wide = height == 0 && self.instruction_set >= InstructionSet::SuperChip;
+        let (rows, row_bytes) = if wide { (16, 2) } else { (height as usize, 1) };
+        let len = rows * row_bytes * self.selected_planes.count_ones() as usize;
+        if self.index as usize + len > MEMORY_SIZE as usize && self.check_operands() {
+            return Err(Chip8Error::SpriteOutOfBounds { index: self.index, len: len as u16 });
+        }
+
+        self.drew = true;
+        if self.warn_on_unaligned_sprite_read && self.index % 2 == 1 {
+            self.events.push(Event::UnalignedSpriteRead { pc: self.pc.wrapping_sub(2), index: self.index });
+        }
+        if wide {
+            self.draw_sprite_rows(vx, vy, rows, row_bytes);
+        } else {
+            self.draw_sprite_fast(vx, vy, height);
+        }
+
+        Ok(())
+    }
+
+    // XORs an 8-pixel-wide sprite from [I] into each selected plane; with both planes
+    // selected the plane 2 rows follow the plane 1 rows in memory (XO-CHIP).
+    // Parts past the right/bottom edge are clipped or wrapped per quirks.clip_sprites.
+    // Reference per-bit version, kept so the fast path can be checked against it.
+    #[doc(hidden)]
+    pub fn draw_sprite_naive(&mut self, vx: usize, vy: usize, height: u8) {
+        self.draw_sprite_rows(vx, vy, height as usize, 1);
+    }
+
+    // draw_sprite_naive for sprites `row_bytes` bytes (8 pixels each) wide
+    fn draw_sprite_rows(&mut self, vx: usize, vy: usize, height: usize, row_bytes: usize) {
+        let width = self.screen_width();
+        let screen_height = self.screen_height();
+        let clip = self.quirks.clip_sprites;
+        let x0 = self.registers[vx] as usize % width;
+        let y0 = self.registers[vy] as usize % screen_height;
+        let mut address = self.index as usize;
+        let mut collision = false;
+
+        for plane in [1u8, 2u8] {
+            if self.selected_planes & plane == 0 {
+                continue;
+            }
+
+            for row in 0..height {
+                let row_address = address;
+                address += row_bytes;
+
+                let mut y = y0 + row;
+                if y >= screen_height {
+                    if clip {
+                        continue;       // clipped at the bottom edge
+                    }
+                    y %= screen_height;
+                }
+
+                for byte in 0..row_bytes {
+                    let sprite_byte = self.memory[(row_address + byte) % MEMORY_SIZE as usize];
+
+                    for col in 0..8 {
+                        let mut x = x0 + byte * 8 + col;
+                        if x >= width {
+                            if clip {
+                                break;  // clipped at the right edge
+                            }
+                            x %= width;
+                        }
+
+                        if sprite_byte & (0x80 >> col) != 0 && self.video.xor_pixel(y * width + x, plane) != 0 {
+                            collision = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        self.set_flag(collision as u8);
+    }
+
+    // with one bit per pixel there is no byte-lane trick to play, the accessors are all there is
+    #[cfg(feature = "packed-video")]
+    #[doc(hidden)]
+    pub fn draw_sprite_fast(&mut self, vx: usize, vy: usize, height: u8) {
+        self.draw_sprite_naive(vx, vy, height);
+    }
+
+    // same result as draw_sprite_naive, but works a whole sprite byte at a time: the byte is
+    // spread into one 0/1 lane per pixel and XORed into 8 pixels of the row as a single u64
+    #[cfg(not(feature = "packed-video"))]
+    #[doc(hidden)]
+    pub fn draw_sprite_fast(&mut self, vx: usize, vy: usize, height: u8) {
+        let width = self.screen_width();
+        let screen_height = self.screen_height();
+        let clip = self.quirks.clip_sprites;
+        let x0 = self.registers[vx] as usize % width;
+        let y0 = self.registers[vy] as usize % screen_height;
+        let rows = if clip { (height as usize).min(screen_height - y0) } else { height as usize };
+        let mut address = self.index as usize;
+        let mut collision: u64 = 0;
+
+        for plane in [1u8, 2u8] {
+            if self.selected_planes & plane == 0 {
+                continue;
+            }
+
+            for row in 0..rows {
+                let bits = self.memory[(address + row) % MEMORY_SIZE as usize];
+                let mask: u64 = SPREAD_BITS[bits as usize] * plane as u64;
+                let row_start = ((y0 + row) % screen_height) * width;
+
+                if x0 + 8 <= width {
+                    let pixels: &mut [u8; 8] = (&mut self.video[row_start + x0..row_start + x0 + 8]).try_into().unwrap();
+                    let word = u64::from_le_bytes(*pixels);
+                    collision |= word & mask;
+                    *pixels = (word ^ mask).to_le_bytes();
+                } else {
+                    // straddles the right edge: the tail is either dropped or wraps to column 0
+                    let cols = if clip { width - x0 } else { 8 };
+                    for (col, lane) in mask.to_le_bytes().into_iter().enumerate().take(cols) {
+                        let pixel = &mut self.video[row_start + (x0 + col) % width];
+                        collision |= (*pixel & lane) as u64;
+                        *pixel ^= lane;
+                    }
+                }
+            }
+
+            address += height as usize;
+        }
+
+        self.set_flag((collision != 0) as u8);
+    }
+
+    // (min_x, min_y, max_x, max_y) a sprite drawn at (x, y) would cover, without drawing it.
+    // When clipping, the box is cut at the screen edge. When wrapping and the sprite crosses
+    // an edge, max < min on that axis: it covers min..=edge and then 0..=max.
+    // A height of 0 is a 16x16 sprite from SUPER-CHIP on, as in drw; before that it draws
+    // nothing and the box is the single row at (x, y).
+    pub fn sprite_bounds(&self, x: u8, y: u8, height: u8) -> (u8, u8, u8, u8) {
+        let width = self.screen_width();
+        let screen_height = self.screen_height();
+        let wide = height == 0 && self.instruction_set >= InstructionSet::SuperChip;
+        let (sprite_width, rows) = if wide { (16, 16) } else { (8, height.max(1) as usize) };
+        let x0 = x as usize % width;
+        let y0 = y as usize % screen_height;
+        let x1 = x0 + sprite_width - 1;
+        let y1 = y0 + rows - 1;
+
+        let (max_x, max_y) = if self.quirks.clip_sprites {
+            (x1.min(width - 1), y1.min(screen_height - 1))
+        } else {
+            (x1 % width, y1 % screen_height)
+        };
+
+        (x0 as u8, y0 as u8, max_x as u8, max_y as u8)
+    }
+
+    fn ret(&mut self) -> Result<(), Chip8Error> {
+        if self.sp == 0 {
+            if self.check_call_ret_pairing {
+                self.events.push(Event::UnmatchedRet { pc: self.pc - 2 });
+            }
+            return Err(Chip8Error::StackUnderflow);
+        }
+
+        self.sp -= 1;
+        // the frame must be one a CALL pushed, at this depth and still holding the address it
+        // pushed. Left in place rather than popped, so stepping back over the RET keeps it.
+        if self.check_call_ret_pairing && self.call_returns.get(self.sp as usize) != Some(&self.stack[self.sp as usize]) {
+            self.events.push(Event::UnmatchedRet { pc: self.pc - 2 });
+        }
+        self.pc = self.stack[self.sp as usize];
+
+        Ok(())
+    }
+}
+
+// Vx..=Vy inclusive, walking backwards when x > y (XO-CHIP 5xy2/5xy3)
+fn register_range(vx: u8, vy: u8) -> Box<dyn Iterator<Item = usize>> {
+    let (vx, vy) = (vx as usize, vy as usize);
+
+    if vx <= vy {
+        Box::new(vx..=vy)
+    } else {
+        Box::new((vy..=vx).rev())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::{Cell, RefCell}, rc::Rc};
+
+    use super::*;
+    use crate::builder::RomBuilder;
+
+    // opcodes as a ROM file stores them, big-endian
+    fn rom(opcodes: &[u16]) -> Vec<u8> {
+        opcodes.iter().flat_map(|opcode| opcode.to_be_bytes()).collect()
+    }
+
+    fn machine(rom: &[u8]) -> Chip8 {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.error_policy = ErrorPolicy::Strict;
+        chip8.load_program(rom);
+        chip8
+    }
+
+    fn run(chip8: &mut Chip8, instructions: usize) {
+        for _ in 0..instructions {
+            chip8.emulate_cycle().unwrap();
+        }
+    }
+
+    #[test]
+    fn delay_timer_counts_down_to_zero() {
+        // LD V0, 0xAA / LD V0, DT
+        let mut chip8 = machine(&rom(&[0x60AA, 0xF007]));
+        chip8.set_delay_timer(5);
+        for _ in 0..5 {
+            chip8.tick_timers();
+        }
+
+        run(&mut chip8, 2);
+        assert_eq!(chip8.registers[0], 0);
+    }
+
+    #[test]
+    fn sound_timer_start_and_stop_events() {
+        let mut chip8 = machine(&[]);
+        chip8.set_sound_timer(2);
+        assert_eq!(chip8.take_events(), vec![Event::SoundStart]);
+
+        chip8.set_sound_timer(1);
+        assert!(chip8.take_events().is_empty());
+        chip8.tick_timers();
+        assert_eq!(chip8.take_events(), vec![Event::SoundStop]);
+    }
+
+    // each CALL goes to the next instruction, so the 17th finds all 16 slots taken
+    fn nested_calls(depth: u16) -> Vec<u8> {
+        let calls: Vec<u16> = (0..depth).map(|i| 0x2000 | (0x202 + i * 2)).collect();
+        rom(&calls)
+    }
+
+    #[test]
+    fn call_past_the_stack_depth_overflows() {
+        let mut chip8 = machine(&nested_calls(17));
+        run(&mut chip8, 16);
+        assert_eq!(chip8.sp, 16);
+        assert_eq!(chip8.emulate_cycle(), Err(Chip8Error::StackOverflow { attempted_depth: 17 }));
+
+        let mut chip8 = machine(&nested_calls(17));
+        chip8.error_policy = ErrorPolicy::Lenient;
+        run(&mut chip8, 17);
+        assert_eq!(chip8.status, MachineStatus::Halted);
+        assert_eq!(chip8.sp, 16);
+    }
+
+    #[test]
+    fn palette_indices_follow_the_planes() {
+        // 0xF0 into plane 1, then 0x3C into plane 2 at the same spot
+        let mut chip8 = machine(&rom(&[0xA20E, 0xF101, 0xD001, 0xA20F, 0xF201, 0xD001, 0x120C, 0xF03C]));
+        chip8.instruction_set = InstructionSet::XoChip;
+        let palette = [Color::new(1, 1, 1), Color::new(2, 2, 2), Color::new(3, 3, 3), Color::new(4, 4, 4)];
+        chip8.set_palette(palette);
+        run(&mut chip8, 6);
+
+        assert_eq!(chip8.video_pixels()[..7], [1, 1, 3, 3, 2, 2, 0]);
+        assert_eq!(chip8.render_to_rgb()[1..5], [palette[1], palette[3], palette[3], palette[2]]);
+    }
+
+    #[test]
+    fn overflowing_add_reports_an_implicit_vf_write() {
+        let mut chip8 = machine(&rom(&[0x6F42, 0x61F0, 0x6220, 0x8124]));
+        chip8.watch_vf_writes = true;
+        run(&mut chip8, 3);
+        assert!(chip8.take_events().is_empty());
+
+        run(&mut chip8, 1);
+        assert_eq!(chip8.take_events(), vec![Event::VfImplicitWrite { pc: 0x206, opcode: 0x8124 }]);
+        assert_eq!(chip8.registers[0xF], 1);
+    }
+
+    #[test]
+    fn any_vf_write_but_a_plain_load_is_reported() {
+        // LD VF, 0x42 / LD VF, V1 / ADD VF, 0x01 / LD I, 0x300 / LD V0..VF, [I]
+        let mut chip8 = machine(&rom(&[0x6F42, 0x8F10, 0x7F01, 0xA300, 0xFF65]));
+        chip8.watch_vf_writes = true;
+        chip8.quirks.load_store_clears_vf = true;
+        run(&mut chip8, 2);
+        assert!(chip8.take_events().is_empty());
+
+        run(&mut chip8, 1);
+        assert_eq!(chip8.take_events(), vec![Event::VfImplicitWrite { pc: 0x204, opcode: 0x7F01 }]);
+
+        // loads VF and then clears it, still one event
+        run(&mut chip8, 2);
+        assert_eq!(chip8.take_events(), vec![Event::VfImplicitWrite { pc: 0x208, opcode: 0xFF65 }]);
+    }
+
+    // random sprites all over the screen (and past its edges) from random memory, drawn by
+    // both paths into two machines that must never disagree
+    #[test]
+    fn fast_draw_matches_naive_draw() {
+        for clip in [true, false] {
+            for planes in [1, 2, 3] {
+                let mut rng = ChaCha12Rng::seed_from_u64(0xC8);
+                let mut naive = Chip8::new();
+                rng.fill(&mut naive.memory[0x300..0x500]);
+                naive.quirks.clip_sprites = clip;
+                naive.selected_planes = planes;
+                let mut fast = Chip8::new();
+                fast.memory = naive.memory;
+                fast.quirks = naive.quirks;
+                fast.selected_planes = planes;
+
+                for i in 0..500 {
+                    let (x, y, height, index) = (rng.random(), rng.random(), rng.random_range(1..=15), rng.random_range(0x300..0x400));
+                    for chip8 in [&mut naive, &mut fast] {
+                        chip8.registers[0] = x;
+                        chip8.registers[1] = y;
+                        chip8.index = index;
+                    }
+                    naive.draw_sprite_naive(0, 1, height);
+                    fast.draw_sprite_fast(0, 1, height);
+
+                    let context = format!("draw {} (clip {}, planes {})", i, clip, planes);
+                    assert_eq!(naive.registers[0xF], fast.registers[0xF], "VF differs after {}", context);
+                    assert!(naive.video == fast.video, "video differs after {}", context);
+                }
+            }
+        }
     }
-}
\ No newline at end of file
+
+    // VF as an operand of 8xy4/8xy5/8xy7: both operands are read first and the flag written
+    // last, so VF always ends up as the flag and V1 as the result of the original values
+    #[test]
+    fn vf_operand_matrix() {
+        // (opcode with x/y, VF before, V1 before, V1 after, VF after)
+        let cases: [(u16, u8, u8, u8, u8); 12] = [
+            (0x8F14, 0xF0, 0x20, 0x20, 1), (0x81F4, 0xF0, 0x20, 0x10, 1), (0x8FF4, 0xF0, 0x20, 0x20, 1),
+            (0x8F14, 0x30, 0x10, 0x10, 0), (0x81F4, 0x30, 0x10, 0x40, 0), (0x8FF4, 0x30, 0x10, 0x10, 0),
+            (0x8F15, 0x30, 0x10, 0x10, 1), (0x81F5, 0x30, 0x10, 0xE0, 0), (0x8FF5, 0x30, 0x10, 0x10, 1),
+            (0x8F17, 0x30, 0x10, 0x10, 0), (0x81F7, 0x30, 0x10, 0x20, 1), (0x8FF7, 0x30, 0x10, 0x10, 1),
+        ];
+
+        for (opcode, vf, v1, v1_after, vf_after) in cases {
+            let mut chip8 = machine(&rom(&[0x6F00 | vf as u16, 0x6100 | v1 as u16, opcode]));
+            run(&mut chip8, 3);
+            assert_eq!((chip8.registers[1], chip8.registers[0xF]), (v1_after, vf_after), "{:04X} with VF={:02X} V1={:02X}", opcode, vf, v1);
+        }
+    }
+
+    #[test]
+    fn halt_padding_stops_a_rom_that_runs_off_its_end() {
+        let mut chip8 = Chip8::new();
+        chip8.error_policy = ErrorPolicy::Strict;
+        chip8.rom_padding = RomPadding::Halt;
+        chip8.load_program(&rom(&[0x6001, 0x7002]));
+        assert_eq!(chip8.memory[0x204..0x206], [0x12, 0x04]);
+
+        run(&mut chip8, 3);
+        assert_eq!(chip8.status, MachineStatus::Halted);
+        assert_eq!((chip8.pc, chip8.registers[0]), (0x204, 3));
+    }
+
+    #[test]
+    fn memory_image_round_trip() {
+        let path = std::env::temp_dir().join(format!("chip8-memory-{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x400..0x402].copy_from_slice(&[0xDE, 0xAD]);
+        chip8.dump_memory(path).unwrap();
+
+        let mut fresh = Chip8::new();
+        fresh.load_memory(path).unwrap();
+        assert_eq!(fresh.memory[0x400..0x402], [0xDE, 0xAD]);
+        assert!(fresh.memory == chip8.memory);
+
+        fs::write(path, [0u8; 100]).unwrap();
+        let error = fresh.load_memory(path).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(fresh.memory[0x400..0x402], [0xDE, 0xAD]);
+        fs::remove_file(path).unwrap();
+    }
+
+    // DRW then three ADDs and a jump back, forever; instructions run per frame
+    fn display_wait_frames(min_instructions: u32) -> Vec<u64> {
+        let mut chip8 = machine(&rom(&[0xD001, 0x7101, 0x7101, 0x7101, 0x1200]));
+        chip8.quirks.display_wait = true;
+        chip8.display_wait_min_instructions = min_instructions;
+        chip8.cycles_per_frame = 20;
+
+        (0..4)
+            .map(|_| {
+                let cycles = chip8.cycles;
+                chip8.run_frame();
+                chip8.cycles - cycles
+            })
+            .collect()
+    }
+
+    #[test]
+    fn display_wait_keeps_a_minimum_throughput() {
+        // without a minimum the frame ends on its DRW: the first frame starts with one, the
+        // rest run the ALU work first
+        assert_eq!(display_wait_frames(0), vec![1, 5, 5, 5]);
+        // one draw per frame, and never fewer instructions than asked for; the frame goes on
+        // past the minimum only until it reaches its DRW
+        assert_eq!(display_wait_frames(3), vec![3, 3, 5, 5]);
+        // the sixth instruction would be the next DRW, which waits for the following frame
+        assert_eq!(display_wait_frames(8), vec![5; 4]);
+    }
+
+    #[test]
+    fn sprite_bounds_near_the_edge() {
+        let mut chip8 = Chip8::new();
+        assert_eq!(chip8.sprite_bounds(10, 4, 5), (10, 4, 17, 8));
+        assert_eq!(chip8.sprite_bounds(60, 29, 5), (60, 29, 63, 31));
+
+        // wrapping: 60..=63 then 0..=3 across, 29..=31 then 0..=1 down
+        chip8.quirks.clip_sprites = false;
+        assert_eq!(chip8.sprite_bounds(60, 29, 5), (60, 29, 3, 1));
+        assert_eq!(chip8.sprite_bounds(64 + 10, 4, 5), (10, 4, 17, 8));
+    }
+
+    #[test]
+    fn sprite_bounds_of_a_16x16_sprite() {
+        let mut chip8 = Chip8::new();
+        assert_eq!(chip8.sprite_bounds(10, 4, 0), (10, 4, 17, 4));
+
+        chip8.instruction_set = InstructionSet::SuperChip;
+        assert_eq!(chip8.sprite_bounds(10, 4, 0), (10, 4, 25, 19));
+        assert_eq!(chip8.sprite_bounds(56, 20, 0), (56, 20, 63, 31));
+
+        // wrapping: 56..=63 then 0..=7 across, 20..=31 then 0..=3 down
+        chip8.quirks.clip_sprites = false;
+        assert_eq!(chip8.sprite_bounds(56, 20, 0), (56, 20, 7, 3));
+    }
+
+    #[test]
+    fn tall_draws_use_up_the_machine_cycle_budget() {
+        let instructions_in_first_frame = |rom: Vec<u8>| {
+            let mut chip8 = machine(&rom);
+            chip8.machine_cycle_budget = Some(COSMAC_VIP_CYCLES_PER_FRAME);
+            chip8.run_frame();
+            chip8.cycles
+        };
+
+        let alu = instructions_in_first_frame(rom(&[0x7001, 0x1200]));
+        let drawing = instructions_in_first_frame(rom(&[0xD00F, 0x7001, 0x1200]));
+        assert_eq!(alu, 54);
+        assert_eq!(drawing, 7);
+        assert!(drawing < alu);
+    }
+
+    #[test]
+    fn sys_is_a_no_op_unless_strict() {
+        let mut chip8 = Chip8::new();
+        chip8.load_program(&[0x01, 0x23, 0x60, 0x05]);
+        chip8.emulate_cycle().unwrap();
+        assert_eq!((chip8.pc, chip8.status), (0x202, MachineStatus::Running));
+        run(&mut chip8, 1);
+        assert_eq!(chip8.registers[0], 5);
+
+        let mut chip8 = machine(&[0x01, 0x23]);
+        assert_eq!(chip8.emulate_cycle(), Err(Chip8Error::InvalidOpcode(0x0123)));
+    }
+
+    #[test]
+    fn odd_sprite_address_warns_and_still_draws() {
+        let rom = RomBuilder::new().ld_i(0x207).drw(0, 0, 1).halt().data(&[0x00, 0xC0]).build();
+        let mut chip8 = machine(&rom);
+        chip8.warn_on_unaligned_sprite_read = true;
+        run(&mut chip8, 2);
+
+        assert_eq!(chip8.take_events(), vec![Event::UnalignedSpriteRead { pc: 0x202, index: 0x207 }]);
+        assert_eq!(chip8.video_pixels()[..3], [1, 1, 0]);
+    }
+
+    #[test]
+    fn random_fill_is_reproducible() {
+        let filled = |seed: u64| {
+            let mut chip8 = Chip8::with_seed(seed);
+            chip8.set_initial_fill(FillPattern::Random);
+            chip8
+        };
+
+        let (a, b) = (filled(42), filled(42));
+        assert!(a.memory == b.memory);
+        assert_eq!(a.registers, b.registers);
+        assert!(a.video == b.video);
+        assert!(a.memory[0x200..].iter().any(|&byte| byte != 0));
+        assert!(filled(43).memory != a.memory);
+    }
+
+    #[test]
+    fn render_callback_only_fires_on_frames_that_drew() {
+        // ten instructions a loop at five a frame: the DRW lands in every other frame
+        let rom = (0..8).fold(RomBuilder::new().drw(0, 0, 1), |rom, _| rom.add(1, 1)).jp(0x200).build();
+        let mut chip8 = machine(&rom);
+        chip8.cycles_per_frame = 5;
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        chip8.set_render_callback(Box::new(move |video, width, height| {
+            assert_eq!((video.len(), width, height), (64 * 32, 64, 32));
+            counter.set(counter.get() + 1);
+        }));
+
+        for _ in 0..6 {
+            chip8.run_frame();
+        }
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn rows_below_the_screen_are_clipped() {
+        let rom = RomBuilder::new().ld(1, 30).ld_i(0x208).drw(0, 1, 5).halt().data(&[0x80; 5]).build();
+        let lit_column = |chip8: &Chip8| (0..32).filter(|&y| chip8.video.get_pixel(y * 64) != 0).collect::<Vec<_>>();
+
+        // the clipped rows would wrap onto the pixels lit at the top, so a collision means they were drawn
+        let mut chip8 = machine(&rom);
+        chip8.video.set_pixel(0, 1);
+        chip8.video.set_pixel(64, 1);
+        run(&mut chip8, 3);
+        assert_eq!(lit_column(&chip8), [0, 1, 30, 31]);
+        assert_eq!(chip8.registers[0xF], 0);
+
+        let mut chip8 = machine(&rom);
+        chip8.video.set_pixel(31 * 64, 1);
+        run(&mut chip8, 3);
+        assert_eq!(lit_column(&chip8), [30]);
+        assert_eq!(chip8.registers[0xF], 1);
+    }
+
+    #[test]
+    fn hires_scroll_then_draw() {
+        let rom = RomBuilder::new().word(0x00FF).ld_i(0x20E).drw(0, 0, 1).ld(0xF, 0x55).word(0x00C4).drw(0, 0, 1).halt().data(&[0x80]).build();
+        let mut chip8 = machine(&rom);
+        chip8.instruction_set = InstructionSet::SuperChip;
+        run(&mut chip8, 4);
+        chip8.drew = false;
+        run(&mut chip8, 1);
+
+        // the scroll moved the pixel 4 rows down, marked the frame as drawn and left VF alone
+        assert!(chip8.drew);
+        assert_eq!(chip8.registers[0xF], 0x55);
+        assert_eq!((chip8.video.get_pixel(0), chip8.video.get_pixel(4 * 128)), (0, 1));
+
+        run(&mut chip8, 1);
+        assert_eq!(chip8.registers[0xF], 0);
+        assert_eq!((chip8.video.get_pixel(0), chip8.video.get_pixel(4 * 128)), (1, 1));
+        assert_eq!(chip8.lit_pixel_count(), 2);
+    }
+
+    #[test]
+    fn lit_pixel_count_matches_the_sprite() {
+        // the 8 glyph: 0xF0, 0x90, 0xF0, 0x90, 0xF0 has 4 + 2 + 4 + 2 + 4 bits set
+        let rom = RomBuilder::new().ld(1, 10).ld_i(0x208).drw(1, 1, 5).halt().data(&[0xF0, 0x90, 0xF0, 0x90, 0xF0]).build();
+        let mut chip8 = machine(&rom);
+        assert_eq!(chip8.lit_pixel_count(), 0);
+        run(&mut chip8, 3);
+        assert_eq!(chip8.lit_pixel_count(), 16);
+
+        // a pixel in both XO-CHIP planes counts once per plane
+        chip8.video.set_pixel(0, 3);
+        assert_eq!(chip8.lit_pixel_count(), 18);
+    }
+
+    #[test]
+    fn jump_into_the_font_is_outside_the_program() {
+        let rom = RomBuilder::new().jp(0x050).build();
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.warn_on_pc_outside_program = true;
+        chip8.load_program(&rom);
+        run(&mut chip8, 2);
+        assert_eq!(chip8.take_events(), vec![Event::PcOutsideProgram { pc: 0x050 }]);
+
+        let mut chip8 = machine(&rom);
+        chip8.warn_on_pc_outside_program = true;
+        run(&mut chip8, 1);
+        assert_eq!(chip8.emulate_cycle(), Err(Chip8Error::PcOutsideProgram(0x050)));
+        assert_eq!(chip8.pc, 0x050);
+    }
+
+    #[test]
+    fn load_store_through_vf_leaves_vf_alone() {
+        let rom = RomBuilder::new().ld(0xF, 0x77).ld_i(0x300).store(0xF).ld_i(0x300).load(0xF).build();
+        let mut chip8 = machine(&rom);
+        run(&mut chip8, 3);
+        assert_eq!(chip8.registers[0xF], 0x77);
+        assert_eq!(chip8.memory[0x30F], 0x77);
+
+        chip8.memory[0x300] = 0x99;
+        run(&mut chip8, 2);
+        assert_eq!(chip8.registers[0xF], 0x77);
+        assert_eq!(chip8.registers[0], 0x99);
+        assert_eq!(chip8.index, 0x310);
+    }
+
+    #[test]
+    fn reseeding_restarts_the_rnd_stream() {
+        let rom = (0..4).fold(RomBuilder::new(), |rom, x| rom.rnd(x, 0xFF)).build();
+        let rolls = |chip8: &mut Chip8| {
+            chip8.pc = 0x200;
+            run(chip8, 4);
+            chip8.registers[..4].to_vec()
+        };
+
+        let mut chip8 = machine(&rom);
+        rolls(&mut chip8);
+        chip8.reseed_rng(99);
+        assert_eq!(chip8.rng_seed(), 99);
+        let after_reseed = rolls(&mut chip8);
+
+        let mut fresh = Chip8::with_seed(99);
+        fresh.load_program(&rom);
+        assert_eq!(after_reseed, rolls(&mut fresh));
+        assert_ne!(after_reseed, rolls(&mut fresh));
+    }
+
+    #[test]
+    fn opcode_cap_stops_the_fourth_draw() {
+        let mut chip8 = machine(&RomBuilder::new().drw(0, 0, 1).jp(0x200).build());
+        chip8.opcode_caps[0xD] = Some(3);
+        run(&mut chip8, 6);
+        assert_eq!(chip8.opcode_count(0xD), 3);
+
+        let outcome = chip8.run_until_breakpoint(10);
+        assert_eq!(outcome.reason, StopReason::Error(Chip8Error::OpcodeCapExceeded { family: 0xD, cap: 3 }));
+        assert_eq!(outcome.context.pc, 0x200);
+        assert_eq!(chip8.pc, 0x200);
+        assert_eq!(chip8.opcode_count(0xD), 3);
+    }
+
+    #[test]
+    fn load_report_describes_the_rom() {
+        let mut chip8 = Chip8::with_seed(0);
+        let report = chip8.load_program(&[0x60, 0x01, 0x70, 0x02, 0x12, 0x04]);
+        assert_eq!(report, LoadReport { start_address: 0x200, bytes_loaded: 6, free_bytes: 4096 - 0x200 - 6 });
+
+        let full = vec![0u8; 4096 - 0x200];
+        let report = chip8.load_program(&full);
+        assert_eq!((report.bytes_loaded, report.free_bytes), (full.len(), 0));
+    }
+
+    #[test]
+    fn unmatched_ret_is_reported() {
+        // CALL 0x206, whose RET comes back to a second RET with nothing left to return to
+        let rom = RomBuilder::new().call(0x206).ret().halt().ret().build();
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.check_call_ret_pairing = true;
+        chip8.load_program(&rom);
+        run(&mut chip8, 2);
+        assert!(chip8.take_events().is_empty());
+
+        run(&mut chip8, 1);
+        assert_eq!(chip8.take_events(), vec![Event::UnmatchedRet { pc: 0x202 }]);
+        assert_eq!(chip8.status, MachineStatus::Halted);
+    }
+
+    #[test]
+    fn ret_through_a_frame_no_call_pushed_is_reported() {
+        // CALL 0x204 / halt / RET
+        let rom = RomBuilder::new().call(0x204).halt().ret().build();
+
+        // the CALL ran before pairing was checked, so the RET has nothing to pair with
+        let mut chip8 = machine(&rom);
+        run(&mut chip8, 1);
+        chip8.check_call_ret_pairing = true;
+        run(&mut chip8, 1);
+        assert_eq!(chip8.take_events(), vec![Event::UnmatchedRet { pc: 0x204 }]);
+        assert_eq!((chip8.pc, chip8.sp), (0x202, 0));
+
+        // the return address was changed under the CALL
+        let mut chip8 = machine(&rom);
+        chip8.check_call_ret_pairing = true;
+        run(&mut chip8, 1);
+        chip8.stack[0] = 0x206;
+        run(&mut chip8, 1);
+        assert_eq!(chip8.take_events(), vec![Event::UnmatchedRet { pc: 0x204 }]);
+
+        // stepping back over a RET and running it again is still paired
+        let mut chip8 = machine(&rom);
+        chip8.check_call_ret_pairing = true;
+        chip8.enable_history(8);
+        run(&mut chip8, 2);
+        assert!(chip8.step_back());
+        run(&mut chip8, 1);
+        assert_eq!(chip8.take_events(), vec![]);
+        assert_eq!(chip8.pc, 0x202);
+    }
+
+    #[test]
+    fn index_effective_wraps_at_the_top_of_memory() {
+        let mut chip8 = machine(&[]);
+        chip8.index = 0x1003;
+        assert_eq!(chip8.index_effective(), 0x003);
+
+        chip8.index = 0xFFFF;
+        assert_eq!(chip8.index_effective(), 0xFFF);
+    }
+
+    #[test]
+    fn post_cycle_hook_sees_every_instruction() {
+        let mut chip8 = machine(&RomBuilder::new().add(0, 2).se(0, 8).jp(0x200).halt().build());
+        let history = Rc::new(RefCell::new(Vec::new()));
+        let recorder = history.clone();
+        chip8.set_post_cycle_hook(Box::new(move |chip8, instruction| {
+            recorder.borrow_mut().push((instruction.address, chip8.registers[0]));
+        }));
+        run(&mut chip8, 12);
+
+        let v0_after_adds: Vec<u8> = history.borrow().iter().filter(|(address, _)| *address == 0x200).map(|&(_, v0)| v0).collect();
+        assert_eq!(v0_after_adds, [2, 4, 6, 8]);
+        assert_eq!(history.borrow().len(), 12);
+        assert_eq!(history.borrow().last(), Some(&(0x206, 8)));
+    }
+
+    #[test]
+    fn stores_into_the_rom_are_logged() {
+        // overwrites its own trailing halt, then stores outside the ROM
+        let rom = RomBuilder::new().ld(0, 0xAB).ld(1, 0xCD).ld_i(0x20C).store(1).ld_i(0x300).store(1).halt().build();
+        let mut chip8 = machine(&rom);
+        chip8.track_self_modifying_writes = true;
+        run(&mut chip8, 6);
+
+        assert_eq!(chip8.self_modifying_writes(), vec![(0x20C, 0xAB), (0x20D, 0xCD)]);
+        assert_eq!(chip8.memory[0x300..0x302], [0xAB, 0xCD]);
+    }
+
+    #[test]
+    fn restart_after_three_halted_frames() {
+        let mut chip8 = machine(&RomBuilder::new().add(0, 1).halt().build());
+        chip8.on_halt = HaltBehavior::RestartAfterFrames(3);
+
+        for _ in 0..2 {
+            assert_eq!(chip8.run_frame().reason, StopReason::Halted);
+            assert_eq!((chip8.status, chip8.registers[0]), (MachineStatus::Halted, 1));
+        }
+        assert_eq!(chip8.run_frame().reason, StopReason::Halted);
+        assert_eq!((chip8.status, chip8.pc, chip8.registers[0]), (MachineStatus::Running, 0x200, 0));
+        assert_eq!(chip8.memory[0x200..0x204], [0x70, 0x01, 0x12, 0x02]);
+
+        run(&mut chip8, 1);
+        assert_eq!(chip8.registers[0], 1);
+    }
+
+    #[test]
+    fn drw_xors_the_sprite_and_flags_collisions() {
+        // 0xA0, 0x40 at (10, 5), then 0xC0 at (10, 5) overlapping one pixel, then at (20, 5)
+        let rom = RomBuilder::new().ld(0, 10).ld(1, 5).ld(2, 20).ld_i(0x212).drw(0, 1, 2).ld_i(0x214).drw(0, 1, 1).drw(2, 1, 1).halt()
+            .data(&[0xA0, 0x40, 0xC0]).build();
+        let mut chip8 = machine(&rom);
+        let row = |chip8: &Chip8, y: usize, from: usize| (from..from + 4).map(|x| chip8.video.get_pixel(y * 64 + x)).collect::<Vec<_>>();
+
+        run(&mut chip8, 5);
+        assert_eq!(chip8.registers[0xF], 0);
+        assert_eq!((row(&chip8, 5, 10), row(&chip8, 6, 10)), (vec![1, 0, 1, 0], vec![0, 1, 0, 0]));
+
+        run(&mut chip8, 2);
+        assert_eq!(chip8.registers[0xF], 1);
+        assert_eq!(row(&chip8, 5, 10), [0, 1, 1, 0]);
+
+        run(&mut chip8, 1);
+        assert_eq!(chip8.registers[0xF], 0);
+        assert_eq!(row(&chip8, 5, 20), [1, 1, 0, 0]);
+    }
+
+    #[test]
+    fn call_pushes_and_ret_pops_the_return_address() {
+        let mut chip8 = machine(&RomBuilder::new().ld(0, 1).call(0x208).add(0, 1).halt().ret().build());
+        run(&mut chip8, 2);
+        assert_eq!((chip8.pc, chip8.sp, chip8.stack[0]), (0x208, 1, 0x204));
+
+        run(&mut chip8, 1);
+        assert_eq!((chip8.pc, chip8.sp), (0x204, 0));
+        run(&mut chip8, 1);
+        assert_eq!(chip8.registers[0], 2);
+    }
+
+    // every register starts out as 0x10 + its number, so a wrong x/y/kk mask picks the wrong value
+    #[test]
+    fn operands_decode_from_the_right_nibbles() {
+        // (opcode, VA after, PC after, VF after)
+        let cases: [(u16, u8, u16, u8); 15] = [
+            (0x3A1A, 0x1A, 0x204, 0x1F), (0x3A1B, 0x1A, 0x202, 0x1F),
+            (0x4A1B, 0x1A, 0x204, 0x1F), (0x4A1A, 0x1A, 0x202, 0x1F),
+            (0x5AB0, 0x1A, 0x202, 0x1F), (0x5AA0, 0x1A, 0x204, 0x1F),
+            (0x6A42, 0x42, 0x202, 0x1F),
+            (0x7A05, 0x1F, 0x202, 0x1F),
+            (0x8AB1, 0x1B, 0x202, 0x1F),
+            (0x8AB2, 0x1A, 0x202, 0x1F),
+            (0x8AB3, 0x01, 0x202, 0x1F),
+            (0x8AB4, 0x35, 0x202, 0),
+            (0x8AB5, 0xFF, 0x202, 0),
+            (0x8ABE, 0x34, 0x202, 0),
+            (0x9AB0, 0x1A, 0x204, 0x1F),
+        ];
+
+        for (opcode, va, pc, vf) in cases {
+            let mut chip8 = machine(&opcode.to_be_bytes());
+            for (n, reg) in chip8.registers.iter_mut().enumerate() {
+                *reg = 0x10 + n as u8;
+            }
+            run(&mut chip8, 1);
+            assert_eq!((chip8.registers[0xA], chip8.pc, chip8.registers[0xF]), (va, pc, vf), "{:04X}", opcode);
+            assert_eq!(chip8.registers[0xB], 0x1B, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn font_digit_above_0xf() {
+        let rom = RomBuilder::new().ld(2, 0x2A).ld_font(2).build();
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_program(&rom);
+        run(&mut chip8, 2);
+        assert_eq!(chip8.index, FONTSET_START_ADDRESS + 0xA * 5);
+
+        let mut chip8 = machine(&rom);
+        run(&mut chip8, 1);
+        assert_eq!(chip8.emulate_cycle(), Err(Chip8Error::InvalidFontDigit(0x2A)));
+    }
+
+    #[test]
+    fn add_byte_wraps_past_0xff() {
+        let mut chip8 = machine(&RomBuilder::new().ld(3, 0xFF).add(3, 0x01).add(3, 0x10).build());
+        run(&mut chip8, 2);
+        assert_eq!(chip8.registers[3], 0x00);
+        assert_eq!(chip8.registers[0xF], 0);
+        run(&mut chip8, 1);
+        assert_eq!(chip8.registers[3], 0x10);
+    }
+
+    #[test]
+    fn or_stores_the_result() {
+        let mut chip8 = machine(&RomBuilder::new().ld(1, 0b1010).ld(2, 0b0101).or(1, 2).build());
+        run(&mut chip8, 3);
+        assert_eq!((chip8.registers[1], chip8.registers[2]), (0b1111, 0b0101));
+    }
+
+    #[test]
+    fn dxy0_near_the_top_of_memory() {
+        let rom = RomBuilder::new().word(0x00FF).ld_i(0xFF0).drw(0, 0, 0).build();
+        let mut chip8 = machine(&rom);
+        chip8.instruction_set = InstructionSet::SuperChip;
+        run(&mut chip8, 2);
+        assert_eq!(chip8.emulate_cycle(), Err(Chip8Error::SpriteOutOfBounds { index: 0xFF0, len: 32 }));
+
+        // lenient machines wrap the sprite read to the start of memory instead
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.instruction_set = InstructionSet::SuperChip;
+        chip8.load_program(&rom);
+        chip8.memory[0xFF0..].fill(0xFF);
+        run(&mut chip8, 3);
+        assert_eq!(chip8.status, MachineStatus::Running);
+        assert_eq!(chip8.lit_pixel_count(), 8 * 16);
+    }
+
+    #[test]
+    fn seeded_rnd_is_reproducible() {
+        let mut chip8 = machine(&RomBuilder::new().rnd(4, 0x0F).rnd(5, 0x0F).rnd(6, 0x0F).build());
+        run(&mut chip8, 3);
+        // recorded from seed 0; only the low nibble survives the mask
+        assert_eq!(chip8.registers[4..7], [0x0F, 0x02, 0x0B]);
+    }
+
+    #[test]
+    fn key_skips_advance_the_pc() {
+        let pc_after = |opcode: u16, pressed: bool| {
+            let mut chip8 = machine(&RomBuilder::new().ld(6, 0xC).word(opcode).build());
+            if pressed {
+                chip8.press_key(0xC);
+            }
+            run(&mut chip8, 2);
+            chip8.pc
+        };
+
+        assert_eq!(pc_after(0xE69E, true), 0x206);
+        assert_eq!(pc_after(0xE69E, false), 0x204);
+        assert_eq!(pc_after(0xE6A1, true), 0x204);
+        assert_eq!(pc_after(0xE6A1, false), 0x206);
+    }
+
+    // (status without the check, status with strict_decode, error under Strict) after the
+    // ROM's last instruction
+    fn operand_check(rom: &[u8], set: InstructionSet) -> (MachineStatus, MachineStatus, Result<(), Chip8Error>) {
+        let instructions = rom.len() / 2;
+        let [unchecked, checked] = [false, true].map(|strict_decode| {
+            let mut chip8 = Chip8::with_seed(0);
+            chip8.instruction_set = set;
+            chip8.strict_decode = strict_decode;
+            chip8.load_program(rom);
+            run(&mut chip8, instructions);
+            chip8.status
+        });
+
+        let mut chip8 = machine(rom);
+        chip8.instruction_set = set;
+        run(&mut chip8, instructions - 1);
+        (unchecked, checked, chip8.emulate_cycle())
+    }
+
+    #[test]
+    fn strict_decode_rejects_a_jump_off_the_end_of_memory() {
+        let (lenient, checked, strict) = operand_check(&RomBuilder::new().jp(0xFFF).build(), InstructionSet::Chip8);
+        assert_eq!((lenient, checked), (MachineStatus::Running, MachineStatus::Halted));
+        assert_eq!(strict, Err(Chip8Error::JumpOutOfRange(0xFFF)));
+    }
+
+    #[test]
+    fn strict_decode_rejects_a_font_digit_above_0xf() {
+        let (lenient, checked, strict) = operand_check(&RomBuilder::new().ld(0, 0x10).ld_font(0).build(), InstructionSet::Chip8);
+        assert_eq!((lenient, checked), (MachineStatus::Running, MachineStatus::Halted));
+        assert_eq!(strict, Err(Chip8Error::InvalidFontDigit(0x10)));
+    }
+
+    #[test]
+    fn strict_decode_rejects_a_store_past_the_top_of_memory() {
+        let (lenient, checked, strict) = operand_check(&RomBuilder::new().ld_i(0xFFE).store(2).build(), InstructionSet::Chip8);
+        assert_eq!((lenient, checked), (MachineStatus::Running, MachineStatus::Halted));
+        assert_eq!(strict, Err(Chip8Error::MemoryOutOfBounds { index: 0xFFE, len: 3 }));
+    }
+
+    #[test]
+    fn strict_decode_rejects_a_schip_flag_register_above_v7() {
+        let (lenient, checked, strict) = operand_check(&RomBuilder::new().word(0xF875).build(), InstructionSet::SuperChip);
+        assert_eq!((lenient, checked), (MachineStatus::Running, MachineStatus::Halted));
+        assert_eq!(strict, Err(Chip8Error::InvalidFlagRegister(8)));
+    }
+
+    #[test]
+    fn key_wait_holds_the_pc_until_a_key_is_down() {
+        let mut chip8 = machine(&RomBuilder::new().ld_key(5).build());
+        run(&mut chip8, 3);
+        assert_eq!((chip8.pc, chip8.status), (0x200, MachineStatus::WaitingForKey(5)));
+
+        chip8.press_key(0xB);
+        run(&mut chip8, 1);
+        assert_eq!((chip8.pc, chip8.status, chip8.registers[5]), (0x202, MachineStatus::Running, 0xB));
+    }
+
+    #[test]
+    fn bcd_splits_254_into_digits() {
+        let mut chip8 = machine(&RomBuilder::new().ld(4, 254).ld_i(0x300).ld_bcd(4).build());
+        run(&mut chip8, 3);
+        assert_eq!(chip8.memory[0x300..0x303], [2, 5, 4]);
+        assert_eq!(chip8.index, 0x300);
+    }
+
+    #[test]
+    fn load_store_cost_grows_per_register() {
+        let chip8 = Chip8::new();
+        assert!(chip8.instruction_cost(0xFF65) > chip8.instruction_cost(0xF065));
+        assert_eq!(chip8.instruction_cost(0xFF65) - chip8.instruction_cost(0xF065), 15 * 28);
+        assert_eq!(chip8.instruction_cost(0xF555), chip8.instruction_cost(0xF565));
+    }
+
+    #[test]
+    fn load_store_index_increment_settings() {
+        let rom = RomBuilder::new().ld(0, 1).ld(1, 2).ld(2, 3).ld_i(0x300).store(2)
+            .ld(0, 0).ld(1, 0).ld(2, 0).ld_i(0x300).load(2).build();
+
+        for (increment, advance) in [(IndexIncrement::XPlusOne, 3), (IndexIncrement::X, 2), (IndexIncrement::None, 0)] {
+            let mut chip8 = machine(&rom);
+            chip8.quirks.load_store_increment = increment;
+            run(&mut chip8, 5);
+            assert_eq!(chip8.memory[0x300..0x303], [1, 2, 3], "{:?}", increment);
+            assert_eq!(chip8.index, 0x300 + advance, "{:?}", increment);
+
+            run(&mut chip8, 5);
+            assert_eq!(chip8.registers[..3], [1, 2, 3], "{:?}", increment);
+            assert_eq!(chip8.index, 0x300 + advance, "{:?}", increment);
+        }
+    }
+
+    #[test]
+    fn font_points_at_the_a_glyph() {
+        let mut chip8 = machine(&RomBuilder::new().ld(5, 0xA).ld_font(5).build());
+        run(&mut chip8, 2);
+        assert_eq!(chip8.index, 0x50 + 0xA * 5);
+        assert_eq!(chip8.memory[chip8.index as usize], 0xF0);
+    }
+
+    // what a SoundSink was asked to do, Some(pattern) for start and None for stop
+    struct RecordingSink(Rc<RefCell<Vec<Option<AudioPattern>>>>);
+
+    impl SoundSink for RecordingSink {
+        fn start(&mut self, pattern: &AudioPattern) {
+            self.0.borrow_mut().push(Some(*pattern));
+        }
+
+        fn stop(&mut self) {
+            self.0.borrow_mut().push(None);
+        }
+    }
+
+    #[test]
+    fn audio_pattern_and_pitch_reach_the_sink() {
+        let pattern: Vec<u8> = (0..16).map(|n| n * 0x11).collect();
+        let rom = RomBuilder::new().ld_i(0x20A).word(0xF002).ld(2, 0x70).word(0xF23A)
+            .halt().data(&pattern).build();
+        let mut chip8 = machine(&rom);
+        chip8.instruction_set = InstructionSet::XoChip;
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        chip8.set_sound_sink(Box::new(RecordingSink(calls.clone())));
+
+        run(&mut chip8, 4);
+        assert_eq!(chip8.audio_pattern.bits[..], pattern[..]);
+        assert_eq!(chip8.audio_pattern.pitch, 0x70);
+        assert!(calls.borrow().is_empty());
+
+        chip8.set_sound_timer(2);
+        assert_eq!(*calls.borrow(), vec![Some(chip8.audio_pattern)]);
+
+        chip8.tick_timers();
+        chip8.tick_timers();
+        assert_eq!(*calls.borrow(), vec![Some(chip8.audio_pattern), None]);
+    }
+
+    #[test]
+    fn timer_opcodes_move_values_in_and_out() {
+        let rom = RomBuilder::new().ld(1, 0x2A).ld_dt(1).ld(2, 0x07).ld_st(2).ld_from_dt(3).build();
+        let mut chip8 = machine(&rom);
+
+        run(&mut chip8, 2);
+        assert_eq!(chip8.delay_timer, 0x2A);
+        run(&mut chip8, 2);
+        assert_eq!(chip8.sound_timer, 0x07);
+        chip8.tick_timers();
+        run(&mut chip8, 1);
+        assert_eq!(chip8.registers[3], 0x29);
+    }
+
+    #[test]
+    fn add_i_sets_vf_only_with_the_overflow_quirk() {
+        let rom = RomBuilder::new().ld(0xF, 0xAA).ld(1, 0x20).ld_i(0xFF0).add_i(1).ld_i(0x100).add_i(1).build();
+
+        for (quirk, overflow_flag) in [(false, 0xAA), (true, 1)] {
+            let mut chip8 = machine(&rom);
+            chip8.quirks.add_i_overflow_flag = quirk;
+
+            run(&mut chip8, 4);
+            assert_eq!(chip8.index, 0x1010);
+            assert_eq!(chip8.registers[0xF], overflow_flag, "quirk {}", quirk);
+
+            run(&mut chip8, 2);
+            assert_eq!(chip8.index, 0x120);
+            assert_eq!(chip8.registers[0xF], if quirk { 0 } else { 0xAA }, "quirk {}", quirk);
+        }
+    }
+
+    #[test]
+    fn timers_tick_down_and_stop_at_zero() {
+        let mut chip8 = machine(&[]);
+        chip8.set_delay_timer(3);
+        chip8.set_sound_timer(2);
+
+        for expected in [2, 1, 0] {
+            chip8.tick_timers();
+            assert_eq!(chip8.delay_timer, expected);
+        }
+        assert_eq!(chip8.sound_timer, 0);
+
+        chip8.tick_timers();
+        assert_eq!((chip8.delay_timer, chip8.sound_timer), (0, 0));
+    }
+
+    #[test]
+    fn shl_moves_the_high_bit_into_vf() {
+        let mut chip8 = machine(&RomBuilder::new().ld(6, 0xC1).shl(6, 0).ld(7, 0x41).shl(7, 0).build());
+        assert!(!chip8.quirks.shift_vy);
+
+        run(&mut chip8, 2);
+        assert_eq!((chip8.registers[6], chip8.registers[0xF]), (0x82, 1));
+        run(&mut chip8, 2);
+        assert_eq!((chip8.registers[7], chip8.registers[0xF]), (0x82, 0));
+    }
+
+    #[test]
+    fn subn_borrow_and_no_borrow() {
+        // V1 = V2 - V1
+        let mut chip8 = machine(&RomBuilder::new().ld(1, 0x10).ld(2, 0x30).subn(1, 2)
+            .ld(3, 0x30).ld(4, 0x10).subn(3, 4).build());
+
+        run(&mut chip8, 3);
+        assert_eq!((chip8.registers[1], chip8.registers[0xF]), (0x20, 1));
+        run(&mut chip8, 3);
+        assert_eq!((chip8.registers[3], chip8.registers[0xF]), (0xE0, 0));
+    }
+
+    #[test]
+    fn sub_and_add_wrap_with_the_right_flag() {
+        let mut chip8 = machine(&RomBuilder::new().ld(1, 0x05).ld(2, 0x10).sub(1, 2)
+            .ld(3, 0xFF).ld(4, 0x01).add_reg(3, 4).build());
+
+        run(&mut chip8, 3);
+        assert_eq!((chip8.registers[1], chip8.registers[0xF]), (0xF5, 0));
+        run(&mut chip8, 3);
+        assert_eq!((chip8.registers[3], chip8.registers[0xF]), (0x00, 1));
+    }
+
+    #[test]
+    fn oversized_rom_is_refused_and_memory_untouched() {
+        let mut chip8 = Chip8::with_seed(0);
+        let before = chip8.memory;
+
+        let path = std::env::temp_dir().join(format!("chip8-oversized-{}.ch8", std::process::id()));
+        let path = path.to_str().unwrap();
+        fs::write(path, vec![0xAB; 4096 - 0x200 + 1]).unwrap();
+        let error = chip8.load_rom(path).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(error.get_ref().and_then(|error| error.downcast_ref::<Chip8Error>()),
+            Some(&Chip8Error::RomTooLarge { size: 3585, capacity: 3584 }));
+        assert_eq!(chip8.memory, before);
+
+        fs::write(path, vec![0xAB; 4096 - 0x200]).unwrap();
+        assert!(chip8.load_rom(path).is_ok());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn bytes_load_at_0x200() {
+        let mut chip8 = Chip8::with_seed(0);
+        let rom = [0x60, 0x1A, 0x12, 0x00, 0x7F];
+
+        chip8.load_rom_from_bytes(&rom).unwrap();
+        assert_eq!(chip8.memory[0x200..0x205], rom);
+        assert_eq!(chip8.memory[0x1FF], 0);
+        assert_eq!(chip8.memory[0x205], 0);
+        assert_eq!(chip8.pc, 0x200);
+    }
+
+    #[test]
+    fn one_frame_runs_cycles_per_frame_instructions_and_ticks_once() {
+        let rom = (0..20).fold(RomBuilder::new(), |rom, _| rom.add(1, 1)).build();
+        let mut chip8 = machine(&rom);
+        chip8.cycles_per_frame = 7;
+        chip8.set_delay_timer(10);
+        chip8.set_sound_timer(3);
+
+        chip8.run_frame();
+        assert_eq!(chip8.pc, 0x200 + 7 * 2);
+        assert_eq!(chip8.registers[1], 7);
+        assert_eq!((chip8.delay_timer, chip8.sound_timer), (9, 2));
+    }
+
+    #[test]
+    fn ret_with_an_empty_stack_underflows() {
+        let mut chip8 = machine(&RomBuilder::new().ret().build());
+        assert_eq!(chip8.emulate_cycle(), Err(Chip8Error::StackUnderflow));
+        assert_eq!(chip8.sp, 0);
+
+        // the seventeenth nested CALL is the one that overflows
+        let mut chip8 = machine(&nested_calls(17));
+        let results: Vec<_> = (0..17).map(|_| chip8.emulate_cycle()).collect();
+        assert!(results[..16].iter().all(Result::is_ok));
+        assert!(matches!(results[16], Err(Chip8Error::StackOverflow { .. })));
+    }
+
+    #[test]
+    fn reset_clears_the_cpu_but_keeps_the_rom() {
+        let rom = RomBuilder::new().ld(0, 9).ld(0xE, 4).ld_i(0x123).ld_dt(0).call(0x20C).halt().ld_font(0).drw(0, 0, 5).build();
+        let mut chip8 = machine(&rom);
+        chip8.key_down(5);
+        run(&mut chip8, 7);
+        assert!(chip8.lit_pixel_count() > 0);
+
+        chip8.reset();
+        assert_eq!(chip8.pc, 0x200);
+        assert_eq!(chip8.registers, [0; 16]);
+        assert_eq!((chip8.index, chip8.sp, chip8.stack), (0, 0, [0; 16]));
+        assert_eq!((chip8.delay_timer, chip8.sound_timer), (0, 0));
+        assert_eq!(chip8.lit_pixel_count(), 0);
+        assert_eq!(chip8.keypad, [false; 16]);
+        assert_eq!(chip8.memory[0x200..0x200 + rom.len()], rom[..]);
+        assert_eq!(chip8.memory[0x50], 0xF0);
+    }
+
+    #[test]
+    fn bnnn_target_follows_the_jump_quirk() {
+        // B310 jumps to 0x310 + V0 classically, 0x310 + V3 with jump_vx
+        let rom = RomBuilder::new().ld(0, 0x04).ld(3, 0x20).jp_v0(0x310).build();
+
+        for (jump_vx, target) in [(false, 0x314), (true, 0x330)] {
+            let mut chip8 = machine(&rom);
+            chip8.quirks.jump_vx = jump_vx;
+            run(&mut chip8, 3);
+            assert_eq!(chip8.pc, target, "jump_vx {}", jump_vx);
+        }
+        assert!(!QuirkProfile::default().jump_vx);
+    }
+
+    #[test]
+    fn shift_quirk_selects_the_source_register() {
+        // V1 = 0x81, V2 = 0x06, then SHR V1, V2 and SHL V3, V2 with V3 = 0x81
+        let rom = RomBuilder::new().ld(1, 0x81).ld(2, 0x06).ld(3, 0x81).shr(1, 2).shl(3, 2).build();
+
+        let mut in_place = machine(&rom);
+        run(&mut in_place, 4);
+        assert_eq!((in_place.registers[1], in_place.registers[0xF]), (0x40, 1));
+        run(&mut in_place, 1);
+        assert_eq!((in_place.registers[3], in_place.registers[0xF]), (0x02, 1));
+
+        let mut from_vy = machine(&rom);
+        from_vy.quirks.shift_vy = true;
+        run(&mut from_vy, 4);
+        assert_eq!((from_vy.registers[1], from_vy.registers[0xF]), (0x03, 0));
+        run(&mut from_vy, 1);
+        assert_eq!((from_vy.registers[3], from_vy.registers[0xF]), (0x0C, 0));
+        assert_eq!(from_vy.registers[2], 0x06);
+    }
+
+    #[test]
+    fn hires_toggles_the_screen_size_and_the_reach_of_drw() {
+        let rom = RomBuilder::new().word(0x00FF).ld(1, 70).ld(2, 40).ld_i(0x212).drw(1, 2, 1)
+            .word(0x00FE).cls().drw(1, 2, 1).halt().data(&[0x80]).build();
+        let mut chip8 = machine(&rom);
+        chip8.instruction_set = InstructionSet::SuperChip;
+        assert_eq!((chip8.screen_width(), chip8.screen_height()), (64, 32));
+
+        run(&mut chip8, 1);
+        assert_eq!((chip8.screen_width(), chip8.screen_height()), (128, 64));
+        run(&mut chip8, 4);
+        assert_eq!(chip8.video.get_pixel(40 * 128 + 70), 1);
+        assert_eq!(chip8.lit_pixel_count(), 1);
+        assert_eq!(chip8.render_to_string().lines().count(), 64);
+
+        // back in lores (70, 40) is off the 64x32 screen and the draw wraps to (6, 8)
+        run(&mut chip8, 1);
+        assert_eq!((chip8.screen_width(), chip8.screen_height()), (64, 32));
+        run(&mut chip8, 2);
+        assert_eq!(chip8.video.get_pixel(8 * 64 + 6), 1);
+        assert_eq!(chip8.lit_pixel_count(), 1);
+        assert_eq!(chip8.render_to_string().lines().next().unwrap().chars().count(), 64);
+    }
+
+    #[test]
+    fn dxy0_draws_a_16x16_sprite() {
+        // rows alternate 0xFFFF and 0x8001: a box of horizontal bars with both side columns lit
+        let sprite: Vec<u8> = (0..16).flat_map(|row| if row % 2 == 0 { [0xFF, 0xFF] } else { [0x80, 0x01] }).collect();
+        let rom = RomBuilder::new().word(0x00FF).ld(0, 100).ld(1, 40).ld_i(0x20E).drw(0, 1, 0).drw(0, 1, 0).halt().data(&sprite).build();
+        let mut chip8 = machine(&rom);
+        chip8.instruction_set = InstructionSet::SuperChip;
+        run(&mut chip8, 5);
+
+        let pixel = |chip8: &Chip8, x: usize, y: usize| chip8.video.get_pixel(y * 128 + x);
+        assert_eq!(chip8.registers[0xF], 0);
+        assert_eq!(chip8.lit_pixel_count(), 8 * 16 + 8 * 2);
+        assert_eq!(pixel(&chip8, 100, 40), 1);
+        assert_eq!(pixel(&chip8, 115, 40), 1);
+        assert_eq!(pixel(&chip8, 100, 41), 1);
+        assert_eq!(pixel(&chip8, 108, 41), 0);
+        assert_eq!(pixel(&chip8, 115, 55), 1);
+        assert_eq!(pixel(&chip8, 116, 40), 0);
+
+        run(&mut chip8, 1);
+        assert_eq!(chip8.registers[0xF], 1);
+        assert_eq!(chip8.lit_pixel_count(), 0);
+    }
+
+    #[test]
+    fn big_font_glyph_on_superchip() {
+        let mut chip8 = machine(&RomBuilder::new().ld(3, 0x7).word(0xF330).build());
+        chip8.instruction_set = InstructionSet::SuperChip;
+        run(&mut chip8, 2);
+
+        let glyph = chip8.index as usize;
+        assert_eq!(chip8.index, BIG_FONTSET_START_ADDRESS + 70);
+        assert_eq!(chip8.memory[glyph..glyph + 10], [0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18]);
+    }
+
+    #[test]
+    fn big_font_is_not_chip8() {
+        let mut chip8 = machine(&RomBuilder::new().word(0xF330).build());
+        assert_eq!(chip8.emulate_cycle(), Err(Chip8Error::InvalidOpcode(0xF330)));
+    }
+
+    // (x, y) of every lit pixel, row by row
+    fn lit_pixels(chip8: &Chip8) -> Vec<(usize, usize)> {
+        let width = chip8.screen_width();
+        (0..width * chip8.screen_height())
+            .filter(|&pixel| chip8.video.get_pixel(pixel) != 0)
+            .map(|pixel| (pixel % width, pixel / width))
+            .collect()
+    }
+
+    #[test]
+    fn scrolls_move_the_pattern_and_blank_what_comes_in() {
+        // glyph 0 at (10, 5), then SCD 3, SCR, SCL, SCL
+        let rom = RomBuilder::new().ld_font(0).ld(1, 10).ld(2, 5).drw(1, 2, 5)
+            .word(0x00C3).word(0x00FB).word(0x00FC).word(0x00FC).halt().build();
+        let mut chip8 = machine(&rom);
+        chip8.instruction_set = InstructionSet::SuperChip;
+        run(&mut chip8, 4);
+        let drawn = lit_pixels(&chip8);
+        assert_eq!(drawn.len(), 14);
+        let shifted = |dx: isize, dy: isize| -> Vec<(usize, usize)> {
+            drawn.iter().map(|&(x, y)| ((x as isize + dx) as usize, (y as isize + dy) as usize)).collect()
+        };
+
+        for (dx, dy) in [(0, 3), (4, 3), (0, 3), (-4, 3)] {
+            run(&mut chip8, 1);
+            assert_eq!(lit_pixels(&chip8), shifted(dx, dy), "after scrolling to ({}, {})", dx, dy);
+        }
+    }
+
+    #[test]
+    fn scrolling_off_the_edge_loses_those_pixels() {
+        // a sprite row at x=60 (clip_sprites cuts it at the edge), SCR pushes it off the right;
+        // then a pixel on row 28 that SCD 15 pushes off the bottom
+        let rom = RomBuilder::new().ld(1, 60).ld(2, 28).ld_i(0x20E).drw(1, 2, 1)
+            .word(0x00FB).word(0x00CF).halt().data(&[0xFF]).build();
+        let mut chip8 = machine(&rom);
+        chip8.instruction_set = InstructionSet::SuperChip;
+        run(&mut chip8, 4);
+        assert_eq!(lit_pixels(&chip8), [(60, 28), (61, 28), (62, 28), (63, 28)]);
+
+        run(&mut chip8, 1);
+        assert!(lit_pixels(&chip8).is_empty());
+
+        chip8.video.set_pixel(28 * 64 + 3, 1);
+        run(&mut chip8, 1);
+        assert!(lit_pixels(&chip8).is_empty());
+    }
+
+    #[test]
+    fn planes_draw_and_clear_independently() {
+        let rom = RomBuilder::new()
+            .ld_i(0x216).word(0xF101).drw(0, 0, 1)
+            .ld_i(0x217).word(0xF201).drw(0, 0, 1)
+            .cls()
+            .word(0xF301).ld_i(0x218).drw(0, 0, 1)
+            .halt().data(&[0xF0, 0x3C, 0x0F, 0x3C]).build();
+        let mut chip8 = machine(&rom);
+        chip8.instruction_set = InstructionSet::XoChip;
+
+        // plane 1 only, then plane 2 only on top of it without a collision
+        run(&mut chip8, 3);
+        assert_eq!(chip8.video_pixels()[..8], [1, 1, 1, 1, 0, 0, 0, 0]);
+        run(&mut chip8, 3);
+        assert_eq!(chip8.video_pixels()[..8], [1, 1, 3, 3, 2, 2, 0, 0]);
+        assert_eq!(chip8.registers[0xF], 0);
+        assert!(chip8.render_to_string().starts_with("\u{2588}\u{2588}\u{2593}\u{2593}\u{2592}\u{2592}  "));
+
+        // CLS with plane 2 selected leaves plane 1 alone
+        run(&mut chip8, 1);
+        assert_eq!(chip8.video_pixels()[..8], [1, 1, 1, 1, 0, 0, 0, 0]);
+
+        // both planes: one byte for plane 1, then one for plane 2
+        run(&mut chip8, 3);
+        assert_eq!(chip8.video_pixels()[..8], [1, 1, 3, 3, 3, 3, 1, 1]);
+        assert_eq!(chip8.registers[0xF], 0);
+    }
+
+    #[test]
+    fn on_sound_fires_on_the_rising_and_falling_edges() {
+        let mut chip8 = machine(&RomBuilder::new().ld(0, 2).ld_st(0).ld(0, 1).ld_st(0).halt().build());
+        let edges = Rc::new(RefCell::new(Vec::new()));
+        let recorder = edges.clone();
+        chip8.on_sound(Box::new(move |on| recorder.borrow_mut().push(on)));
+
+        run(&mut chip8, 2);
+        assert!(chip8.is_beeping());
+        assert_eq!(*edges.borrow(), [true]);
+
+        // reloading while it plays isn't an edge
+        run(&mut chip8, 2);
+        assert_eq!(*edges.borrow(), [true]);
+
+        chip8.tick_timers();
+        assert!(!chip8.is_beeping());
+        assert_eq!(*edges.borrow(), [true, false]);
+
+        chip8.tick_timers();
+        assert_eq!(*edges.borrow(), [true, false]);
+    }
+
+    #[test]
+    fn display_wait_lets_one_of_two_draws_run_per_frame() {
+        let rom = RomBuilder::new().drw(0, 0, 1).drw(1, 1, 1).add(2, 1).halt().build();
+
+        for (display_wait, first_frame_draws) in [(false, 2), (true, 1)] {
+            let mut chip8 = machine(&rom);
+            chip8.quirks.display_wait = display_wait;
+            chip8.display_wait_min_instructions = 10;
+            chip8.cycles_per_frame = 10;
+
+            chip8.run_frame();
+            assert_eq!(chip8.opcode_count(0xD), first_frame_draws, "display_wait {}", display_wait);
+            if display_wait {
+                assert_eq!(chip8.pc, 0x202);
+            }
+
+            chip8.run_frame();
+            assert_eq!(chip8.opcode_count(0xD), 2, "display_wait {}", display_wait);
+        }
+    }
+
+    #[test]
+    fn save_range_stores_registers_forwards_and_backwards() {
+        let rom = RomBuilder::new().ld(1, 0x11).ld(2, 0x22).ld(3, 0x33).ld_i(0x300).word(0x5132).word(0x5312).build();
+        let mut chip8 = machine(&rom);
+        chip8.instruction_set = InstructionSet::XoChip;
+        run(&mut chip8, 5);
+        assert_eq!(chip8.memory[0x300..0x303], [0x11, 0x22, 0x33]);
+        assert_eq!(chip8.index, 0x300);
+
+        run(&mut chip8, 1);
+        assert_eq!(chip8.memory[0x300..0x303], [0x33, 0x22, 0x11]);
+    }
+
+    #[test]
+    fn load_range_reads_registers() {
+        let rom = RomBuilder::new().ld_i(0x206).word(0x5243).halt().data(&[0xAA, 0xBB, 0xCC]).build();
+        let mut chip8 = machine(&rom);
+        chip8.instruction_set = InstructionSet::XoChip;
+        run(&mut chip8, 2);
+        assert_eq!(chip8.registers[2..5], [0xAA, 0xBB, 0xCC]);
+    }
+
+    #[test]
+    fn five_family_low_nibble_rejected_on_chip8() {
+        let mut chip8 = machine(&[0x50, 0x01]);
+        assert_eq!(chip8.emulate_cycle(), Err(Chip8Error::InvalidOpcode(0x5001)));
+
+        let mut chip8 = machine(&[0x51, 0x22]);
+        assert_eq!(chip8.emulate_cycle(), Err(Chip8Error::InvalidOpcode(0x5122)));
+    }
+
+    #[test]
+    fn save_range_is_a_tracked_checked_write() {
+        let rom = RomBuilder::new().ld(0, 0x77).ld_i(0x200).word(0x5012).build();
+        let mut chip8 = machine(&rom);
+        chip8.instruction_set = InstructionSet::XoChip;
+        chip8.track_self_modifying_writes = true;
+        run(&mut chip8, 3);
+        assert_eq!(chip8.self_modifying_writes(), vec![(0x200, 0x77), (0x201, 0x00)]);
+
+        let rom = RomBuilder::new().ld_i(0xFFF).word(0x5022).build();
+        let mut chip8 = machine(&rom);
+        chip8.instruction_set = InstructionSet::XoChip;
+        run(&mut chip8, 1);
+        assert_eq!(chip8.emulate_cycle(), Err(Chip8Error::MemoryOutOfBounds { index: 0xFFF, len: 3 }));
+    }
+}
@@ -0,0 +1,389 @@
+use std::fmt;
+use std::io::Write;
+
+use crate::chip8::{Chip8, EventKind, MachineStatus};
+use crate::disasm::disassemble_opcode;
+use crate::error::Chip8Error;
+
+// why a run loop handed control back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    FrameComplete,                  // run_frame/run finished normally
+    Breakpoint(u16),                // about to execute a breakpointed address
+    Watchpoint { address: u16, old: u8, new: u8 }, // the last instruction changed a watched byte
+    Event(EventKind),               // the last instruction raised a watched event
+    Condition,                      // the last instruction met a run_until_* condition
+    Halted,                         // machine status is Halted
+    Paused,                         // pause() was called, nothing runs until resume()
+    CycleBudgetExhausted,           // ran the number of instructions asked for
+    Error(Chip8Error),              // only under ErrorPolicy::Strict
+}
+
+impl StopReason {
+    // stopped for the debugger's sake rather than because the run was over
+    pub fn is_break(&self) -> bool {
+        matches!(self, StopReason::Breakpoint(_) | StopReason::Watchpoint { .. } | StopReason::Event(_))
+    }
+}
+
+// one entry in the debugger's breakpoint list, with the label it was added under if any
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BreakpointInfo {
+    Pc { address: u16, label: Option<String> },        // before executing this address
+    Watchpoint { address: u16, label: Option<String> }, // after a write changes this memory address
+    Event { kind: EventKind, label: Option<String> },  // after an instruction raises this event
+}
+
+// where the machine was when the run loop stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StopContext {
+    pub pc: u16,                    // for errors, the address of the failing instruction
+    pub cycles: u64,
+    pub opcode: u16,                // next to execute, or the one that failed
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunOutcome {
+    pub reason: StopReason,
+    pub context: StopContext,
+}
+
+impl fmt::Display for RunOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let StopContext { pc, cycles, opcode } = self.context;
+        match self.reason {
+            StopReason::FrameComplete => write!(f, "frame complete")?,
+            StopReason::Breakpoint(address) => write!(f, "breakpoint at {:#05X}", address)?,
+            StopReason::Watchpoint { address, old, new } => {
+                write!(f, "watchpoint at {:#05X}, {:#04X} -> {:#04X}", address, old, new)?
+            }
+            StopReason::Event(kind) => write!(f, "event {:?}", kind)?,
+            StopReason::Condition => write!(f, "condition met")?,
+            StopReason::Halted => write!(f, "halted")?,
+            StopReason::Paused => write!(f, "paused")?,
+            StopReason::CycleBudgetExhausted => write!(f, "cycle budget exhausted")?,
+            StopReason::Error(error) => write!(f, "error: {}", error)?,
+        }
+        write!(f, " (PC={:#06X}, opcode={:#06X}, cycles={})", pc, opcode, cycles)
+    }
+}
+
+impl Chip8 {
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address, None);
+    }
+
+    // the label is only for display, see breakpoints(); re-adding replaces it
+    pub fn add_breakpoint_named(&mut self, address: u16, label: &str) {
+        self.breakpoints.insert(address, Some(label.to_string()));
+    }
+
+    // false if there was no breakpoint at that address
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address).is_some()
+    }
+
+    pub fn add_watchpoint(&mut self, address: u16) {
+        self.watchpoints.insert(address, None);
+    }
+
+    pub fn add_watchpoint_named(&mut self, address: u16, label: &str) {
+        self.watchpoints.insert(address, Some(label.to_string()));
+    }
+
+    pub fn remove_watchpoint(&mut self, address: u16) -> bool {
+        self.watchpoints.remove(&address).is_some()
+    }
+
+    pub fn add_event_breakpoint(&mut self, kind: EventKind) {
+        self.event_breakpoints.insert(kind, None);
+    }
+
+    pub fn add_event_breakpoint_named(&mut self, kind: EventKind, label: &str) {
+        self.event_breakpoints.insert(kind, Some(label.to_string()));
+    }
+
+    pub fn remove_event_breakpoint(&mut self, kind: EventKind) -> bool {
+        self.event_breakpoints.remove(&kind).is_some()
+    }
+
+    // PC breakpoints, watchpoints and event breakpoints alike
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+        self.event_breakpoints.clear();
+    }
+
+    // every kind in one list: PC breakpoints, then watchpoints, then events, each in order
+    pub fn breakpoints(&self) -> Vec<BreakpointInfo> {
+        let pcs = self.breakpoints.iter()
+            .map(|(&address, label)| BreakpointInfo::Pc { address, label: label.clone() });
+        let watches = self.watchpoints.iter()
+            .map(|(&address, label)| BreakpointInfo::Watchpoint { address, label: label.clone() });
+        let events = self.event_breakpoints.iter()
+            .map(|(&kind, label)| BreakpointInfo::Event { kind, label: label.clone() });
+
+        pcs.chain(watches).chain(events).collect()
+    }
+
+    // freezes the machine where it is, Fx0A waits included; run_* return StopReason::Paused
+    // straight away and the timers stop until resume()
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // up to `frames` frames, stopping early for breakpoints, errors or a halt
+    pub fn run(&mut self, frames: u32) -> RunOutcome {
+        let mut outcome = self.outcome(StopReason::FrameComplete, self.pc, self.fetch_opcode(self.pc));
+        for _ in 0..frames {
+            outcome = self.run_frame();
+            if outcome.reason != StopReason::FrameComplete {
+                break;
+            }
+        }
+        outcome
+    }
+
+    // instruction by instruction (frames still tick every cycles_per_frame) until a
+    // breakpoint, an error, a halt, or `max_cycles` instructions have run
+    pub fn run_until_breakpoint(&mut self, max_cycles: u64) -> RunOutcome {
+        self.run_loop(Some(max_cycles), false, &|_| false)
+    }
+
+    // the PC breakpoint the run stopped at, or None if it stopped for any other reason
+    pub fn run_until_break(&mut self, max_cycles: usize) -> Option<u16> {
+        match self.run_until_breakpoint(max_cycles as u64).reason {
+            StopReason::Breakpoint(address) => Some(address),
+            _ => None,
+        }
+    }
+
+    // as run_until_breakpoint, but also stops right after the instruction that makes
+    // registers[reg] equal value
+    pub fn run_until_register(&mut self, reg: u8, value: u8, max_cycles: u64) -> RunOutcome {
+        let reg = reg as usize & 0xF;
+        self.run_loop(Some(max_cycles), false, &|chip8| chip8.registers[reg] == value)
+    }
+
+    // runs one instruction and writes it as "0x0202: DRW V0, V1, 5 ; VF=1"; the VF note only
+    // appears when the instruction set VF as a flag. Errors and halts are noted the same way.
+    pub fn step_and_log(&mut self, writer: &mut impl Write) -> std::io::Result<()> {
+        let pc = self.pc;
+        let opcode = self.fetch_opcode(pc);
+        let mut line = format!("{:#06X}: {}", pc, disassemble_opcode(opcode));
+
+        if self.paused {
+            line.push_str(" ; paused");
+        } else if self.status == MachineStatus::Halted {
+            line.push_str(" ; halted");
+        } else {
+            self.flag_written = false;
+            let result = self.emulate_cycle();
+
+            if self.flag_written {
+                line.push_str(&format!(" ; VF={}", self.registers[0xF]));
+            }
+            if let Err(error) = result {
+                line.push_str(&format!(" ; error: {}", error));
+            }
+        }
+
+        writeln!(writer, "{}", line)
+    }
+
+    // shared by every run_* entry point; a breakpoint we just stopped on is stepped over,
+    // otherwise resuming would stop on it again straight away
+    // `condition` is checked after every instruction, stopping with StopReason::Condition
+    pub(crate) fn run_loop(&mut self, max_cycles: Option<u64>, until_frame_end: bool, condition: &dyn Fn(&Chip8) -> bool) -> RunOutcome {
+        let start_cycles = self.cycles;
+
+        let outcome = loop {
+            let pc = self.pc;
+            let opcode = self.fetch_opcode(pc);
+            let halted = self.status == MachineStatus::Halted;
+
+            if self.paused {
+                break self.outcome(StopReason::Paused, pc, opcode);
+            }
+            if !halted && self.breakpoints.contains_key(&pc) && self.stopped_at != Some(pc) {
+                self.stopped_at = Some(pc);
+                break self.outcome(StopReason::Breakpoint(pc), pc, opcode);
+            }
+            if halted && !until_frame_end {
+                break self.outcome(StopReason::Halted, pc, opcode);
+            }
+            if max_cycles.is_some_and(|max| self.cycles - start_cycles >= max) {
+                break self.outcome(StopReason::CycleBudgetExhausted, pc, opcode);
+            }
+
+            let watched: Vec<(u16, u8)> = self.watchpoints.keys()
+                .map(|&address| (address, self.memory[address as usize % self.memory.len()]))
+                .collect();
+            let events_before = self.events.len();
+            let cycles_before = self.cycles;
+
+            self.stopped_at = None;
+            let step = self.step_in_frame();
+
+            let frame_done = match step {
+                Ok(frame_done) => frame_done,
+                Err(error) => break self.outcome(StopReason::Error(error), pc, opcode),
+            };
+            if let Some(reason) = self.data_or_event_break(&watched, events_before) {
+                break self.outcome(reason, self.pc, self.fetch_opcode(self.pc));
+            }
+
+            if self.cycles != cycles_before && condition(self) {
+                break self.outcome(StopReason::Condition, self.pc, self.fetch_opcode(self.pc));
+            }
+            if frame_done && until_frame_end {
+                let reason = if self.status == MachineStatus::Halted {
+                    StopReason::Halted
+                } else {
+                    StopReason::FrameComplete
+                };
+                break self.outcome(reason, self.pc, self.fetch_opcode(self.pc));
+            }
+        };
+
+        if self.log_stops {
+            eprintln!("stopped: {}", outcome);
+        }
+
+        outcome
+    }
+
+    fn data_or_event_break(&self, watched: &[(u16, u8)], events_before: usize) -> Option<StopReason> {
+        for &(address, old) in watched {
+            let new = self.memory[address as usize % self.memory.len()];
+            if new != old {
+                return Some(StopReason::Watchpoint { address, old, new });
+            }
+        }
+
+        self.events.get(events_before..)?
+            .iter()
+            .map(|event| event.kind())
+            .find(|kind| self.event_breakpoints.contains_key(kind))
+            .map(StopReason::Event)
+    }
+
+    fn outcome(&self, reason: StopReason, pc: u16, opcode: u16) -> RunOutcome {
+        RunOutcome {
+            reason,
+            context: StopContext { pc, cycles: self.cycles, opcode },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::RomBuilder;
+    use crate::chip8::ErrorPolicy;
+    use crate::video::PixelBuffer;
+
+    fn machine(rom: &[u8]) -> Chip8 {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.error_policy = ErrorPolicy::Strict;
+        chip8.load_rom_from_bytes(rom).unwrap();
+        chip8
+    }
+
+    #[test]
+    fn run_until_break_stops_two_instructions_in() {
+        let rom = RomBuilder::new().ld(0, 1).ld(1, 2).ld(2, 3).halt().build();
+        let mut chip8 = machine(&rom);
+        chip8.add_breakpoint(0x204);
+
+        assert_eq!(chip8.run_until_break(100), Some(0x204));
+        assert_eq!(chip8.pc, 0x204);
+        assert_eq!((chip8.registers[0], chip8.registers[1], chip8.registers[2]), (1, 2, 0));
+
+        chip8.remove_breakpoint(0x204);
+        assert_eq!(chip8.run_until_break(100), None);
+    }
+
+    #[test]
+    fn breakpoint_stop_context() {
+        let rom = RomBuilder::new().ld(0, 1).ld(1, 2).ld(2, 3).halt().build();
+        let mut chip8 = machine(&rom);
+        chip8.add_breakpoint(0x204);
+
+        let outcome = chip8.run_until_breakpoint(100);
+        assert_eq!(outcome.reason, StopReason::Breakpoint(0x204));
+        assert_eq!(outcome.context, StopContext { pc: 0x204, cycles: 2, opcode: 0x6203 });
+        assert_eq!(outcome.to_string(), "breakpoint at 0x204 (PC=0x0204, opcode=0x6203, cycles=2)");
+    }
+
+    #[test]
+    fn breakpoints_lists_every_kind() {
+        let mut chip8 = machine(&[]);
+        chip8.add_event_breakpoint(EventKind::SoundStart);
+        chip8.add_watchpoint(0x300);
+        chip8.add_breakpoint(0x208);
+
+        assert_eq!(chip8.breakpoints(), vec![
+            BreakpointInfo::Pc { address: 0x208, label: None },
+            BreakpointInfo::Watchpoint { address: 0x300, label: None },
+            BreakpointInfo::Event { kind: EventKind::SoundStart, label: None },
+        ]);
+
+        chip8.clear_breakpoints();
+        assert!(chip8.breakpoints().is_empty());
+    }
+
+    #[test]
+    fn run_until_register_stops_on_the_counting_loop() {
+        let mut chip8 = machine(&RomBuilder::new().add(0, 3).jp(0x200).build());
+
+        let outcome = chip8.run_until_register(0, 12, 1000);
+        assert_eq!(outcome.reason, StopReason::Condition);
+        assert_eq!((chip8.registers[0], chip8.cycles, chip8.pc), (12, 7, 0x202));
+
+        let outcome = chip8.run_until_register(0, 13, 50);
+        assert_eq!(outcome.reason, StopReason::CycleBudgetExhausted);
+    }
+
+    #[test]
+    fn step_and_log_writes_disassembly_with_flag_notes() {
+        let rom = RomBuilder::new().ld(0, 0xFF).ld(1, 0x01).add_reg(0, 1).ld_i(0x20C).drw(0, 0, 1).halt().data(&[0x80]).build();
+        let mut chip8 = machine(&rom);
+        chip8.video.set_pixel(0, 1);
+        let mut log: Vec<u8> = Vec::new();
+        for _ in 0..7 {
+            chip8.step_and_log(&mut log).unwrap();
+        }
+
+        let log = String::from_utf8(log).unwrap();
+        assert_eq!(log.lines().collect::<Vec<_>>(), [
+            "0x0200: LD V0, 0xFF",
+            "0x0202: LD V1, 0x01",
+            "0x0204: ADD V0, V1 ; VF=1",
+            "0x0206: LD I, 0x20C",
+            "0x0208: DRW V0, V0, 1 ; VF=1",
+            "0x020A: JP 0x20A",
+            "0x020A: JP 0x20A ; halted",
+        ]);
+    }
+
+    #[test]
+    fn breakpoint_labels_round_trip() {
+        let mut chip8 = machine(&[]);
+        chip8.add_breakpoint_named(0x20A, "player death");
+        chip8.add_watchpoint_named(0x310, "score");
+
+        assert_eq!(chip8.breakpoints(), vec![
+            BreakpointInfo::Pc { address: 0x20A, label: Some("player death".to_string()) },
+            BreakpointInfo::Watchpoint { address: 0x310, label: Some("score".to_string()) },
+        ]);
+    }
+}
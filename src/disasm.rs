@@ -0,0 +1,183 @@
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use crate::instruction::{Instruction, decode};
+
+// mnemonic for a single opcode, e.g. "LD V0, 0x1A"; unknown opcodes come out as "DW 0x____"
+pub fn disassemble_opcode(opcode: u16) -> String {
+    format_opcode(opcode, &|address| format!("0x{:03X}", address))
+}
+
+// (address, mnemonic) for every word of a ROM loaded at `start`, unknown opcodes as
+// "DW 0x____" and an odd trailing byte as "DB 0x__"
+pub fn disassemble(rom: &[u8], start: u16) -> Vec<(u16, String)> {
+    rom.chunks(2)
+        .enumerate()
+        .map(|(i, word)| {
+            let address: u16 = start.wrapping_add((i * 2) as u16);
+            let text = match *word {
+                [high, low] => disassemble_opcode(((high as u16) << 8) | low as u16),
+                [byte] => format!("DB 0x{:02X}", byte),
+                _ => unreachable!(),
+            };
+            (address, text)
+        })
+        .collect()
+}
+
+// two passes: collect every 1nnn/2nnn target inside the ROM, then emit the listing
+// with those targets labelled and the jumps/calls rewritten to use the labels
+pub fn disassemble_rom_labeled(bytes: &[u8], base: u16) -> String {
+    let end = base as usize + bytes.len();
+    let in_rom = |address: u16| {
+        (address as usize) >= base as usize
+            && (address as usize) + 1 < end
+            && (address - base).is_multiple_of(2)
+    };
+
+    let mut labels: BTreeSet<u16> = BTreeSet::new();
+    for word in bytes.chunks_exact(2) {
+        let opcode: u16 = ((word[0] as u16) << 8) | word[1] as u16;
+        let target: u16 = opcode & 0x0FFF;
+
+        if matches!(opcode & 0xF000, 0x1000 | 0x2000) && in_rom(target) {
+            labels.insert(target);
+        }
+    }
+
+    let label_or_address = |address: u16| {
+        if labels.contains(&address) {
+            label_name(address)
+        } else {
+            format!("0x{:03X}", address)
+        }
+    };
+
+    let mut listing = String::new();
+    for (i, word) in bytes.chunks(2).enumerate() {
+        let address: u16 = base.wrapping_add((i * 2) as u16);
+
+        if labels.contains(&address) {
+            let _ = writeln!(listing, "{}:", label_name(address));
+        }
+
+        if word.len() < 2 {
+            let _ = writeln!(listing, "    DB 0x{:02X}", word[0]);
+            break;
+        }
+
+        let opcode: u16 = ((word[0] as u16) << 8) | word[1] as u16;
+        let text = match opcode & 0xF000 {
+            0x1000 | 0x2000 => format_opcode(opcode, &label_or_address),
+            _ => disassemble_opcode(opcode),
+        };
+        let _ = writeln!(listing, "    {}", text);
+    }
+
+    listing
+}
+
+fn label_name(address: u16) -> String {
+    format!("L_0x{:03X}", address)
+}
+
+fn format_opcode(opcode: u16, address: &dyn Fn(u16) -> String) -> String {
+    let Some(instruction) = decode(opcode) else {
+        return unknown(opcode);
+    };
+
+    match instruction {
+        Instruction::Sys(nnn) => format!("SYS {}", address(nnn)),
+        Instruction::ClearScreen => "CLS".to_string(),
+        Instruction::Return => "RET".to_string(),
+        Instruction::ScrollDown(n) => format!("SCD {}", n),
+        Instruction::ScrollUp(n) => format!("SCU {}", n),
+        Instruction::ScrollRight => "SCR".to_string(),
+        Instruction::ScrollLeft => "SCL".to_string(),
+        Instruction::Exit => "EXIT".to_string(),
+        Instruction::LowRes => "LOW".to_string(),
+        Instruction::HighRes => "HIGH".to_string(),
+        Instruction::Jump(nnn) => format!("JP {}", address(nnn)),
+        Instruction::Call(nnn) => format!("CALL {}", address(nnn)),
+        Instruction::SkipEqByte { x, byte } => format!("SE V{:X}, 0x{:02X}", x, byte),
+        Instruction::SkipNeByte { x, byte } => format!("SNE V{:X}, 0x{:02X}", x, byte),
+        Instruction::SkipEqReg { x, y } => format!("SE V{:X}, V{:X}", x, y),
+        Instruction::SaveRange { x, y } => format!("SAVE V{:X} - V{:X}", x, y),
+        Instruction::LoadRange { x, y } => format!("LOAD V{:X} - V{:X}", x, y),
+        Instruction::SetReg { x, byte } => format!("LD V{:X}, 0x{:02X}", x, byte),
+        Instruction::AddByte { x, byte } => format!("ADD V{:X}, 0x{:02X}", x, byte),
+        Instruction::Move { x, y } => format!("LD V{:X}, V{:X}", x, y),
+        Instruction::Or { x, y } => format!("OR V{:X}, V{:X}", x, y),
+        Instruction::And { x, y } => format!("AND V{:X}, V{:X}", x, y),
+        Instruction::Xor { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+        Instruction::AddReg { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+        Instruction::Sub { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+        Instruction::ShiftRight { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+        Instruction::SubN { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+        Instruction::ShiftLeft { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+        Instruction::SkipNeReg { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+        Instruction::SetIndex(nnn) => format!("LD I, {}", address(nnn)),
+        Instruction::JumpOffset(nnn) => format!("JP V0, {}", address(nnn)),
+        Instruction::Random { x, byte } => format!("RND V{:X}, 0x{:02X}", x, byte),
+        Instruction::DrawSprite { x, y, n } => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        Instruction::SkipKeyDown { x } => format!("SKP V{:X}", x),
+        Instruction::SkipKeyUp { x } => format!("SKNP V{:X}", x),
+        Instruction::Plane(n) => format!("PLANE {}", n),
+        Instruction::Audio => "AUDIO".to_string(),
+        Instruction::GetDelay { x } => format!("LD V{:X}, DT", x),
+        Instruction::WaitKey { x } => format!("LD V{:X}, K", x),
+        Instruction::SetDelay { x } => format!("LD DT, V{:X}", x),
+        Instruction::SetSound { x } => format!("LD ST, V{:X}", x),
+        Instruction::AddIndex { x } => format!("ADD I, V{:X}", x),
+        Instruction::Font { x } => format!("LD F, V{:X}", x),
+        Instruction::BigFont { x } => format!("LD HF, V{:X}", x),
+        Instruction::Bcd { x } => format!("LD B, V{:X}", x),
+        Instruction::Pitch { x } => format!("PITCH V{:X}", x),
+        Instruction::Store { x } => format!("LD [I], V{:X}", x),
+        Instruction::Load { x } => format!("LD V{:X}, [I]", x),
+        Instruction::StoreFlags { x } => format!("LD R, V{:X}", x),
+        Instruction::LoadFlags { x } => format!("LD V{:X}, R", x),
+    }
+}
+
+fn unknown(opcode: u16) -> String {
+    format!("DW 0x{:04X}", opcode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loop_target_gets_a_label() {
+        // 0x200: LD V0, 0x00 / 0x202: ADD V0, 0x01 / 0x204: SE V0, 0x05 / 0x206: JP 0x202 / 0x208: CALL 0x300
+        let rom = [0x60, 0x00, 0x70, 0x01, 0x30, 0x05, 0x12, 0x02, 0x23, 0x00];
+        let listing = disassemble_rom_labeled(&rom, 0x200);
+
+        assert_eq!(
+            listing.lines().collect::<Vec<_>>(),
+            ["    LD V0, 0x00", "L_0x202:", "    ADD V0, 0x01", "    SE V0, 0x05", "    JP L_0x202", "    CALL 0x300"]
+        );
+    }
+
+    #[test]
+    fn listing_wraps_at_the_top_of_the_address_space() {
+        let listing = disassemble_rom_labeled(&[0x00, 0xE0, 0x00, 0xE0], 0xFFFE);
+        assert_eq!(listing, "    CLS\n    CLS\n");
+    }
+
+    #[test]
+    fn tiny_rom_lines_up_with_its_mnemonics() {
+        // hand-assembled, with an unknown 8xy8 and an odd byte at the end
+        let rom = [0x60, 0x1A, 0xA2, 0x0A, 0xD0, 0x15, 0x80, 0x18, 0x12, 0x00, 0xFF];
+
+        assert_eq!(disassemble(&rom, 0x200), [
+            (0x200, "LD V0, 0x1A".to_string()),
+            (0x202, "LD I, 0x20A".to_string()),
+            (0x204, "DRW V0, V1, 5".to_string()),
+            (0x206, "DW 0x8018".to_string()),
+            (0x208, "JP 0x200".to_string()),
+            (0x20A, "DB 0xFF".to_string()),
+        ]);
+    }
+}
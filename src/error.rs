@@ -0,0 +1,66 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Error {
+    StackOverflow { attempted_depth: usize },   // CALL with all 16 stack slots in use
+    StackUnderflow,                             // RET with nothing to return to
+    InvalidOpcode(u16),                         // not part of the active instruction set
+    PcOutsideProgram(u16),                      // fetch from outside the ROM, see warn_on_pc_outside_program
+    OpcodeCapExceeded { family: u8, cap: u64 }, // opcode family ran more often than opcode_caps allows
+    InvalidFontDigit(u8),                       // Fx29/Fx30 with Vx above 0xF; this and the rest are strict_decode checks
+    SpriteOutOfBounds { index: u16, len: u16 }, // DRW sprite data past the end of memory
+    MemoryOutOfBounds { index: u16, len: u16 }, // Fx33/Fx55/Fx65 running past the end of memory
+    JumpOutOfRange(u16),                        // JP/CALL/JP V0 target with no full opcode behind it
+    InvalidFlagRegister(u8),                    // SCHIP Fx75/Fx85 with x above 7
+    InvalidSaveState,                           // load_state got bytes save_state didn't write
+    RomTooLarge { size: usize, capacity: usize }, // ROM bigger than the memory above 0x200
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::StackOverflow { attempted_depth } => {
+                write!(f, "stack overflow: CALL would nest {} levels deep", attempted_depth)
+            }
+            Chip8Error::StackUnderflow => write!(f, "stack underflow: RET with an empty stack"),
+            Chip8Error::InvalidOpcode(opcode) => write!(f, "invalid opcode {:04X}", opcode),
+            Chip8Error::PcOutsideProgram(pc) => write!(f, "PC {:03X} is outside the loaded program", pc),
+            Chip8Error::OpcodeCapExceeded { family, cap } => {
+                write!(f, "{:X}xxx opcodes exceeded their cap of {}", family, cap)
+            }
+            Chip8Error::InvalidFontDigit(digit) => write!(f, "no font glyph for {:#04X}", digit),
+            Chip8Error::SpriteOutOfBounds { index, len } => {
+                write!(f, "{}-byte sprite at I={:03X} runs past the end of memory", len, index)
+            }
+            Chip8Error::MemoryOutOfBounds { index, len } => {
+                write!(f, "{}-byte access at I={:03X} runs past the end of memory", len, index)
+            }
+            Chip8Error::JumpOutOfRange(target) => write!(f, "jump to {:04X} is outside memory", target),
+            Chip8Error::InvalidFlagRegister(reg) => write!(f, "SCHIP has no flag register for V{:X}", reg),
+            Chip8Error::InvalidSaveState => write!(f, "save state is truncated or from another version"),
+            Chip8Error::RomTooLarge { size, capacity } => {
+                write!(f, "ROM is {} bytes but only {} fit in memory", size, capacity)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+// why Chip8::from_state_report couldn't read a report
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    Malformed { line: usize, text: String }, // 1-based line number and its contents
+    Missing(&'static str),                   // a required item never showed up
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Malformed { line, text } => write!(f, "can't read line {}: {:?}", line, text),
+            ParseError::Missing(item) => write!(f, "state report has no {}", item),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
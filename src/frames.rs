@@ -0,0 +1,93 @@
+use crate::chip8::{Chip8, Event};
+use crate::debugger::{RunOutcome, StopReason};
+
+// what one run_frame did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameResult {
+    pub drew: bool,                 // a DRW or CLS ran, the screen needs presenting
+    pub events: Vec<Event>,         // drained from the machine at the end of the frame
+    pub outcome: RunOutcome
+}
+
+// runs one frame per item; ends after a frame stopped by any kind of breakpoint, an error or a pause,
+// a halted machine keeps producing (timer-only) frames
+pub struct Frames<'a> {
+    chip8: &'a mut Chip8,
+    done: bool
+}
+
+impl Iterator for Frames<'_> {
+    type Item = FrameResult;
+
+    fn next(&mut self) -> Option<FrameResult> {
+        if self.done {
+            return None;
+        }
+
+        let outcome = self.chip8.run_frame();
+        self.done = !matches!(outcome.reason, StopReason::FrameComplete | StopReason::Halted);
+
+        Some(FrameResult {
+            drew: self.chip8.drew,
+            events: self.chip8.take_events(),
+            outcome
+        })
+    }
+}
+
+impl Chip8 {
+    pub fn frames(&mut self) -> Frames<'_> {
+        Frames { chip8: self, done: false }
+    }
+
+    // fewer than n results only if a breakpoint or error cut the run short
+    pub fn step_frames(&mut self, n: u32) -> Vec<FrameResult> {
+        self.frames().take(n as usize).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::RomBuilder;
+
+    #[test]
+    fn step_frames_reports_which_frames_drew() {
+        // ten instructions a loop at five a frame: the DRW lands in every other frame
+        let mut rom = vec![0xD0, 0x01];
+        for _ in 0..8 {
+            rom.extend([0x71, 0x01]);   // ADD V1, 1
+        }
+        rom.extend([0x12, 0x00]);
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x200..0x200 + rom.len()].copy_from_slice(&rom);
+        chip8.cycles_per_frame = 5;
+
+        let results = chip8.step_frames(5);
+        assert_eq!(results.len(), 5);
+        let drew: Vec<bool> = results.iter().map(|frame| frame.drew).collect();
+        assert_eq!(drew, [true, false, true, false, true]);
+        assert!(results.iter().all(|frame| frame.outcome.reason == StopReason::FrameComplete));
+    }
+
+    #[test]
+    fn scripted_frame_input() {
+        // counts in V1 while key 0 is held: three times a frame at nine instructions a frame
+        let rom = RomBuilder::new().sknp(0).add(1, 1).jp(0x200).build();
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.memory[0x200..0x200 + rom.len()].copy_from_slice(&rom);
+        chip8.cycles_per_frame = 9;
+        let mut held = [false; 16];
+        held[0] = true;
+
+        chip8.set_frame_input(held);
+        assert!(!chip8.is_key_down(0));
+        assert_eq!(chip8.step_frames(1).len(), 1);
+        assert_eq!(chip8.registers[1], 3);
+
+        chip8.set_frame_input([false; 16]);
+        chip8.step_frames(1);
+        assert_eq!(chip8.registers[1], 3);
+        assert!(!chip8.is_key_down(0));
+    }
+}
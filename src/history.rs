@@ -0,0 +1,441 @@
+use std::collections::VecDeque;
+
+use crate::audio::AudioPattern;
+use crate::chip8::{Chip8, MachineStatus};
+use crate::video::{PixelBuffer, VIDEO_SIZE};
+
+// one piece of machine state an instruction changed, with the value before and after
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Register { reg: u8, old: u8, new: u8 },
+    Index { old: u16, new: u16 },
+    Pc { old: u16, new: u16 },
+    Sp { old: u8, new: u8 },
+    Stack { slot: u8, old: u16, new: u16 },
+    DelayTimer { old: u8, new: u8 },
+    SoundTimer { old: u8, new: u8 },
+    Memory { address: u16, old: u8, new: u8 },
+    Video { pixel: u16, old: u8, new: u8 },
+    Planes { old: u8, new: u8 },
+    Hires { old: bool, new: bool },
+    Status { old: MachineStatus, new: MachineStatus },
+    Audio { old: AudioPattern, new: AudioPattern },
+    RplFlag { slot: u8, old: u8, new: u8 },
+    OpcodeCount { family: u8, old: u64, new: u64 },
+    RngPosition { old: u128, new: u128 }, // words into the RND stream, see Chip8::reseed_rng
+    FrameCycle { old: u32, new: u32 },
+    FrameMachineCycles { old: u32, new: u32 },
+    TimerAccumulator { old: u64, new: u64 }, // f64 bits, so Change stays Eq
+    VblankWait { old: bool, new: bool },
+    Drew { old: bool, new: bool },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    pub pc: u16,                    // address the instruction was fetched from
+    pub opcode: u16,
+    pub changes: Vec<Change>
+}
+
+// bounded log of per-instruction deltas; old values let us step back, new values replay forward
+#[derive(Debug, Default)]
+pub struct ExecutionHistory {
+    capacity: usize,                // 0 = not recording
+    entries: VecDeque<HistoryEntry>,
+    undone: Vec<HistoryEntry>       // stepped back over, available to replay until the next real step
+}
+
+impl ExecutionHistory {
+    pub fn is_recording(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries.iter()
+    }
+
+    pub fn last(&self) -> Option<&HistoryEntry> {
+        self.entries.back()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.undone.clear();
+    }
+
+    fn push(&mut self, entry: HistoryEntry) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+        self.undone.clear();
+    }
+}
+
+// full copy of the state an instruction can touch, diffed against the machine afterwards
+pub(crate) struct Capture {
+    pc: u16,
+    registers: [u8; 16],
+    index: u16,
+    sp: u8,
+    stack: [u16; 16],
+    delay_timer: u8,
+    sound_timer: u8,
+    selected_planes: u8,
+    hires: bool,
+    status: MachineStatus,
+    audio_pattern: AudioPattern,
+    rpl_flags: [u8; 16],
+    opcode_counts: [u64; 16],
+    rng_position: u128,
+    frame_cycle: u32,
+    frame_machine_cycles: u32,
+    timer_accumulator: f64,
+    vblank_wait: bool,
+    drew: bool,
+    memory: Box<[u8]>,
+    video: Box<[u8]>
+}
+
+impl Capture {
+    pub(crate) fn take(chip8: &Chip8) -> Self {
+        Capture {
+            pc: chip8.pc,
+            registers: chip8.registers,
+            index: chip8.index,
+            sp: chip8.sp,
+            stack: chip8.stack,
+            delay_timer: chip8.delay_timer,
+            sound_timer: chip8.sound_timer,
+            selected_planes: chip8.selected_planes,
+            hires: chip8.hires,
+            status: chip8.status,
+            audio_pattern: chip8.audio_pattern,
+            rpl_flags: chip8.rpl_flags,
+            opcode_counts: chip8.opcode_counts,
+            rng_position: chip8.rng.get_word_pos(),
+            frame_cycle: chip8.frame_cycle,
+            frame_machine_cycles: chip8.frame_machine_cycles,
+            timer_accumulator: chip8.timer_accumulator,
+            vblank_wait: chip8.vblank_wait,
+            drew: chip8.drew,
+            memory: chip8.memory.into(),
+            video: (0..VIDEO_SIZE).map(|pixel| chip8.video.get_pixel(pixel)).collect()
+        }
+    }
+
+    pub(crate) fn diff(&self, chip8: &Chip8) -> HistoryEntry {
+        let mut changes = Vec::new();
+
+        for (reg, (&old, &new)) in self.registers.iter().zip(chip8.registers.iter()).enumerate() {
+            if old != new {
+                changes.push(Change::Register { reg: reg as u8, old, new });
+            }
+        }
+        if self.index != chip8.index {
+            changes.push(Change::Index { old: self.index, new: chip8.index });
+        }
+        if self.pc != chip8.pc {
+            changes.push(Change::Pc { old: self.pc, new: chip8.pc });
+        }
+        if self.sp != chip8.sp {
+            changes.push(Change::Sp { old: self.sp, new: chip8.sp });
+        }
+        for (slot, (&old, &new)) in self.stack.iter().zip(chip8.stack.iter()).enumerate() {
+            if old != new {
+                changes.push(Change::Stack { slot: slot as u8, old, new });
+            }
+        }
+        if self.delay_timer != chip8.delay_timer {
+            changes.push(Change::DelayTimer { old: self.delay_timer, new: chip8.delay_timer });
+        }
+        if self.sound_timer != chip8.sound_timer {
+            changes.push(Change::SoundTimer { old: self.sound_timer, new: chip8.sound_timer });
+        }
+        for (address, (&old, &new)) in self.memory.iter().zip(chip8.memory.iter()).enumerate() {
+            if old != new {
+                changes.push(Change::Memory { address: address as u16, old, new });
+            }
+        }
+        for (pixel, &old) in self.video.iter().enumerate() {
+            let new = chip8.video.get_pixel(pixel);
+            if old != new {
+                changes.push(Change::Video { pixel: pixel as u16, old, new });
+            }
+        }
+        if self.selected_planes != chip8.selected_planes {
+            changes.push(Change::Planes { old: self.selected_planes, new: chip8.selected_planes });
+        }
+        if self.hires != chip8.hires {
+            changes.push(Change::Hires { old: self.hires, new: chip8.hires });
+        }
+        if self.status != chip8.status {
+            changes.push(Change::Status { old: self.status, new: chip8.status });
+        }
+        if self.audio_pattern != chip8.audio_pattern {
+            changes.push(Change::Audio { old: self.audio_pattern, new: chip8.audio_pattern });
+        }
+        for (slot, (&old, &new)) in self.rpl_flags.iter().zip(chip8.rpl_flags.iter()).enumerate() {
+            if old != new {
+                changes.push(Change::RplFlag { slot: slot as u8, old, new });
+            }
+        }
+        for (family, (&old, &new)) in self.opcode_counts.iter().zip(chip8.opcode_counts.iter()).enumerate() {
+            if old != new {
+                changes.push(Change::OpcodeCount { family: family as u8, old, new });
+            }
+        }
+        let rng_position = chip8.rng.get_word_pos();
+        if self.rng_position != rng_position {
+            changes.push(Change::RngPosition { old: self.rng_position, new: rng_position });
+        }
+        if self.frame_cycle != chip8.frame_cycle {
+            changes.push(Change::FrameCycle { old: self.frame_cycle, new: chip8.frame_cycle });
+        }
+        if self.frame_machine_cycles != chip8.frame_machine_cycles {
+            changes.push(Change::FrameMachineCycles { old: self.frame_machine_cycles, new: chip8.frame_machine_cycles });
+        }
+        if self.timer_accumulator.to_bits() != chip8.timer_accumulator.to_bits() {
+            changes.push(Change::TimerAccumulator {
+                old: self.timer_accumulator.to_bits(),
+                new: chip8.timer_accumulator.to_bits(),
+            });
+        }
+        if self.vblank_wait != chip8.vblank_wait {
+            changes.push(Change::VblankWait { old: self.vblank_wait, new: chip8.vblank_wait });
+        }
+        if self.drew != chip8.drew {
+            changes.push(Change::Drew { old: self.drew, new: chip8.drew });
+        }
+
+        HistoryEntry { pc: self.pc, opcode: chip8.opcode, changes }
+    }
+}
+
+impl Chip8 {
+    // start recording the last `capacity` instructions (0 turns recording off)
+    pub fn enable_history(&mut self, capacity: usize) {
+        self.history.capacity = capacity;
+        self.history.clear();
+    }
+
+    pub fn history(&self) -> &ExecutionHistory {
+        &self.history
+    }
+
+    pub(crate) fn record_history(&mut self, before: Capture) {
+        let entry = before.diff(self);
+        self.history.push(entry);
+    }
+
+    // the frame bookkeeping around an instruction (opening a frame, counting the instruction,
+    // the timer tick that ends the frame) happens outside it; it is folded into the latest
+    // entry so stepping back over that instruction undoes the bookkeeping as well
+    pub(crate) fn record_frame_end(&mut self, before: Capture) {
+        let changes = before.diff(self).changes;
+        self.fold_into_last_entry(changes);
+    }
+
+    // cheaper than a Capture for the two counters bumped after every instruction
+    pub(crate) fn record_frame_count(&mut self, old_cycle: u32, old_machine_cycles: u32) {
+        let mut changes = vec![Change::FrameCycle { old: old_cycle, new: self.frame_cycle }];
+        if old_machine_cycles != self.frame_machine_cycles {
+            changes.push(Change::FrameMachineCycles { old: old_machine_cycles, new: self.frame_machine_cycles });
+        }
+        self.fold_into_last_entry(changes);
+    }
+
+    fn fold_into_last_entry(&mut self, changes: impl IntoIterator<Item = Change>) {
+        if let Some(entry) = self.history.entries.back_mut() {
+            entry.changes.extend(changes);
+        }
+    }
+
+    // undoes the most recent recorded instruction, false if there is nothing to undo
+    pub fn step_back(&mut self) -> bool {
+        let Some(entry) = self.history.entries.pop_back() else {
+            return false;
+        };
+
+        for change in entry.changes.iter().rev() {
+            self.apply_change(change, false);
+        }
+        self.cycles = self.cycles.saturating_sub(1);
+        self.history.undone.push(entry);
+
+        true
+    }
+
+    // re-applies the most recently undone instruction without executing it again
+    pub fn replay_forward(&mut self) -> bool {
+        let Some(entry) = self.history.undone.pop() else {
+            return false;
+        };
+
+        for change in entry.changes.iter() {
+            self.apply_change(change, true);
+        }
+        self.cycles += 1;
+        self.history.entries.push_back(entry);
+
+        true
+    }
+
+    fn apply_change(&mut self, change: &Change, forward: bool) {
+        fn pick<T>(forward: bool, old: T, new: T) -> T {
+            if forward { new } else { old }
+        }
+
+        match *change {
+            Change::Register { reg, old, new } => self.registers[reg as usize] = pick(forward, old, new),
+            Change::Index { old, new } => self.index = pick(forward, old, new),
+            Change::Pc { old, new } => self.pc = pick(forward, old, new),
+            Change::Sp { old, new } => self.sp = pick(forward, old, new),
+            Change::Stack { slot, old, new } => self.stack[slot as usize] = pick(forward, old, new),
+            Change::DelayTimer { old, new } => self.delay_timer = pick(forward, old, new),
+            Change::SoundTimer { old, new } => self.sound_timer = pick(forward, old, new),
+            Change::Memory { address, old, new } => self.memory[address as usize] = pick(forward, old, new),
+            Change::Video { pixel, old, new } => self.video.set_pixel(pixel as usize, pick(forward, old, new)),
+            Change::Planes { old, new } => self.selected_planes = pick(forward, old, new),
+            Change::Hires { old, new } => self.hires = pick(forward, old, new),
+            Change::Status { old, new } => self.status = pick(forward, old, new),
+            Change::Audio { old, new } => self.audio_pattern = pick(forward, old, new),
+            Change::RplFlag { slot, old, new } => self.rpl_flags[slot as usize] = pick(forward, old, new),
+            Change::OpcodeCount { family, old, new } => self.opcode_counts[family as usize] = pick(forward, old, new),
+            Change::RngPosition { old, new } => self.rng.set_word_pos(pick(forward, old, new)),
+            Change::FrameCycle { old, new } => self.frame_cycle = pick(forward, old, new),
+            Change::FrameMachineCycles { old, new } => self.frame_machine_cycles = pick(forward, old, new),
+            Change::TimerAccumulator { old, new } => self.timer_accumulator = f64::from_bits(pick(forward, old, new)),
+            Change::VblankWait { old, new } => self.vblank_wait = pick(forward, old, new),
+            Change::Drew { old, new } => self.drew = pick(forward, old, new),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::RomBuilder;
+    use crate::chip8::InstructionSet;
+
+    // touches registers, I, the delay timer, memory, audio, RPL flags and the RNG, with
+    // frames ending (and timers ticking) every three instructions
+    fn machine() -> Chip8 {
+        let rom = RomBuilder::new()
+            .ld(0, 10)
+            .ld_dt(0)
+            .rnd(1, 0xFF)
+            .ld_i(0x300)
+            .word(0xF002)
+            .ld(2, 80)
+            .word(0xF23A)
+            .word(0xF175)
+            .store(2)
+            .rnd(3, 0xFF)
+            .add(0, 1)
+            .halt()
+            .build();
+
+        let mut chip8 = Chip8::with_seed(7);
+        chip8.instruction_set = InstructionSet::XoChip;
+        chip8.cycles_per_frame = 3;
+        chip8.load_program(&rom);
+        chip8.enable_history(64);
+        chip8
+    }
+
+    fn step(chip8: &mut Chip8) {
+        chip8.run_until_breakpoint(1);
+    }
+
+    #[test]
+    fn step_back_retraces_the_forward_run() {
+        let mut chip8 = machine();
+        let mut states = vec![(Capture::take(&chip8), chip8.cycles)];
+        for _ in 0..11 {
+            step(&mut chip8);
+            states.push((Capture::take(&chip8), chip8.cycles));
+        }
+        assert_eq!(chip8.history().len(), 11);
+
+        for (state, cycles) in states.iter().rev().skip(1) {
+            assert!(chip8.step_back());
+            assert_eq!(state.diff(&chip8).changes, vec![]);
+            assert_eq!(chip8.cycles, *cycles);
+        }
+        assert!(!chip8.step_back());
+
+        for (state, cycles) in states.iter().skip(1) {
+            assert!(chip8.replay_forward());
+            assert_eq!(state.diff(&chip8).changes, vec![]);
+            assert_eq!(chip8.cycles, *cycles);
+        }
+    }
+
+    #[test]
+    fn stepping_back_over_rnd_replays_the_same_number() {
+        let mut chip8 = machine();
+        for _ in 0..3 {
+            step(&mut chip8);
+        }
+        let first = chip8.registers[1];
+
+        assert!(chip8.step_back());
+        step(&mut chip8);
+        assert_eq!(chip8.registers[1], first);
+    }
+
+    #[test]
+    fn frame_end_tick_belongs_to_the_last_instruction() {
+        let mut chip8 = machine();
+        for _ in 0..3 {
+            step(&mut chip8);
+        }
+        assert_eq!(chip8.delay_timer, 9);
+
+        let changes = &chip8.history().last().unwrap().changes;
+        assert!(changes.contains(&Change::DelayTimer { old: 10, new: 9 }));
+        chip8.step_back();
+        assert_eq!(chip8.delay_timer, 10);
+    }
+
+    #[test]
+    fn stepping_back_across_a_frame_boundary_resumes_the_same_frames() {
+        let mut replayed = machine();
+        let mut straight = machine();
+        for chip8 in [&mut replayed, &mut straight] {
+            chip8.run_frame();
+            chip8.run_frame();
+        }
+
+        // back over the second frame and the last instruction of the first
+        for _ in 0..4 {
+            assert!(replayed.step_back());
+        }
+        assert_eq!(replayed.frame_cycle, 2);
+        assert_eq!(replayed.delay_timer, 10);
+        for _ in 0..4 {
+            assert!(replayed.replay_forward());
+        }
+
+        assert_eq!(replayed.delay_timer, straight.delay_timer);
+        assert_eq!(replayed.frame_cycle, straight.frame_cycle);
+        assert_eq!(replayed.timer_accumulator, straight.timer_accumulator);
+        assert_eq!(replayed.run_frame(), straight.run_frame());
+        assert_eq!(replayed.delay_timer, straight.delay_timer);
+        assert_eq!(replayed.registers, straight.registers);
+    }
+}
@@ -0,0 +1,243 @@
+use crate::chip8::Chip8;
+
+// something the run loop can ask for the current keypad state
+pub trait InputSource {
+    fn poll(&mut self) -> [bool; 16];
+}
+
+// plays back a fixed sequence of keypad states, one per poll, then holds the last one
+pub struct ScriptedInput {
+    states: Vec<[bool; 16]>,
+    next: usize
+}
+
+impl ScriptedInput {
+    pub fn new(states: Vec<[bool; 16]>) -> Self {
+        ScriptedInput { states, next: 0 }
+    }
+}
+
+impl InputSource for ScriptedInput {
+    fn poll(&mut self) -> [bool; 16] {
+        if self.states.is_empty() {
+            return [false; 16];
+        }
+
+        let state = self.states[self.next.min(self.states.len() - 1)];
+        self.next += 1;
+        state
+    }
+}
+
+// turns a held key into a press, a gap of `initial_delay` polls, then a one-poll press
+// every `repeat_interval` polls; counts polls, which is one per frame unless
+// input_poll_interval asks for more
+pub struct AutoRepeat<I: InputSource> {
+    inner: I,
+    initial_delay: u32,
+    repeat_interval: u32,
+    held_for: [u32; 16]             // polls each key has been continuously down, 0 = up
+}
+
+impl<I: InputSource> AutoRepeat<I> {
+    pub fn new(inner: I, initial_delay: u32, repeat_interval: u32) -> Self {
+        AutoRepeat {
+            inner,
+            initial_delay,
+            repeat_interval: repeat_interval.max(1),
+            held_for: [0; 16]
+        }
+    }
+
+    pub fn into_inner(self) -> I {
+        self.inner
+    }
+}
+
+impl<I: InputSource> InputSource for AutoRepeat<I> {
+    fn poll(&mut self) -> [bool; 16] {
+        let raw = self.inner.poll();
+        let mut keys = [false; 16];
+
+        for (key, &down) in raw.iter().enumerate() {
+            if !down {
+                self.held_for[key] = 0;
+                continue;
+            }
+
+            let held = self.held_for[key];
+            keys[key] = held == 0
+                || (held >= self.initial_delay && (held - self.initial_delay).is_multiple_of(self.repeat_interval));
+            self.held_for[key] = held.saturating_add(1);
+        }
+
+        keys
+    }
+}
+
+// keys are 0x0-0xF; anything else is ignored by press/release and never reads as down.
+// An attached InputSource overwrites these on its next poll.
+impl Chip8 {
+    pub fn press_key(&mut self, key: u8) {
+        if let Some(down) = self.keypad.get_mut(key as usize) {
+            *down = true;
+        }
+    }
+
+    pub fn release_key(&mut self, key: u8) {
+        if let Some(down) = self.keypad.get_mut(key as usize) {
+            *down = false;
+        }
+    }
+
+    // the names frontends tend to use for press_key/release_key
+    pub fn key_down(&mut self, key: u8) {
+        self.press_key(key);
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        self.release_key(key);
+    }
+
+    // the whole keypad for the next frame, applied as it starts (after any InputSource poll)
+    pub fn set_frame_input(&mut self, keys: [bool; 16]) {
+        self.pending_input = Some(keys);
+    }
+
+    pub fn is_key_down(&self, key: u8) -> bool {
+        self.keypad.get(key as usize).copied().unwrap_or(false)
+    }
+}
+
+// the usual QWERTY layout for the hex keypad, left four columns of the keyboard:
+//   1 2 3 4      1 2 3 C
+//   Q W E R  ->  4 5 6 D
+//   A S D F      7 8 9 E
+//   Z X C V      A 0 B F
+pub fn map_key(c: char) -> Option<u8> {
+    let key = match c.to_ascii_lowercase() {
+        '1' => 0x1, '2' => 0x2, '3' => 0x3, '4' => 0xC,
+        'q' => 0x4, 'w' => 0x5, 'e' => 0x6, 'r' => 0xD,
+        'a' => 0x7, 's' => 0x8, 'd' => 0x9, 'f' => 0xE,
+        'z' => 0xA, 'x' => 0x0, 'c' => 0xB, 'v' => 0xF,
+        _ => return None,
+    };
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    #[test]
+    fn input_is_repolled_mid_frame() {
+        // the fifth state is only reached if a frame polls more than once
+        let mut states = vec![[false; 16]; 5];
+        states[4][3] = true;
+
+        for (interval, repolled) in [(None, false), (Some(2), true)] {
+            let mut chip8 = Chip8::new();
+            // ADD V0, 1 / JP 0x200
+            chip8.memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]);
+            chip8.set_input_source(Box::new(ScriptedInput::new(states.clone())));
+            chip8.input_poll_interval = interval;
+
+            // polled as the frame opens, then before its 3rd, 5th, 7th and 9th instructions
+            chip8.run_frame();
+            assert_eq!(chip8.keypad[3], repolled, "interval {:?}", interval);
+        }
+    }
+
+    #[test]
+    fn key_changes_are_reported_against_the_frame_start() {
+        let mut five = [false; 16];
+        five[5] = true;
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]); // ADD V0, 1 / JP 0x200
+        chip8.set_input_source(Box::new(ScriptedInput::new(vec![[false; 16], five, five, [false; 16]])));
+
+        let changes: Vec<Vec<(u8, bool)>> = (0..4)
+            .map(|_| {
+                chip8.run_frame();
+                chip8.keypad_changes_since_last_frame()
+            })
+            .collect();
+        assert_eq!(changes, vec![vec![], vec![(5, true)], vec![], vec![(5, false)]]);
+    }
+
+    #[test]
+    fn auto_repeat_presses_waits_then_repeats() {
+        let mut held = [false; 16];
+        held[2] = true;
+        let mut states = vec![held; 8];
+        states.extend([[false; 16], held]);
+        let mut input = AutoRepeat::new(ScriptedInput::new(states), 3, 2);
+
+        let presses: Vec<bool> = (0..10).map(|_| input.poll()[2]).collect();
+        assert_eq!(presses, [true, false, false, true, false, true, false, true, false, true]);
+    }
+
+    #[test]
+    fn skp_and_key_wait_read_the_bool_keypad() {
+        // LD V0, 7 / SKP V0 / LD V1, 0xAA / LD V2, K / SKP V0 / LD V3, 0xBB / JP 0x20C
+        let rom = [0x60, 0x07, 0xE0, 0x9E, 0x61, 0xAA, 0xF2, 0x0A, 0xE0, 0x9E, 0x63, 0xBB, 0x12, 0x0C];
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x200..0x200 + rom.len()].copy_from_slice(&rom);
+        chip8.press_key(7);
+        for _ in 0..3 {
+            chip8.emulate_cycle().unwrap();
+        }
+        assert_eq!((chip8.registers[1], chip8.registers[2]), (0, 7));
+
+        chip8.release_key(7);
+        assert!(!chip8.is_key_down(7));
+        for _ in 0..2 {
+            chip8.emulate_cycle().unwrap();
+        }
+        assert_eq!(chip8.registers[3], 0xBB);
+    }
+
+    #[test]
+    fn key_down_and_up_set_and_clear_the_keypad() {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.key_down(0xA);
+        assert!(chip8.is_key_down(0xA));
+        assert_eq!(chip8.keypad.iter().filter(|&&down| down).count(), 1);
+
+        chip8.key_up(0xA);
+        assert!(!chip8.is_key_down(0xA));
+    }
+
+    #[test]
+    fn out_of_range_keys_are_ignored() {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.key_down(0x10);
+        chip8.press_key(0xFF);
+        assert_eq!(chip8.keypad, [false; 16]);
+        assert!(!chip8.is_key_down(0x10));
+        chip8.key_up(0x10);
+    }
+
+    #[test]
+    fn map_key_covers_the_qwerty_grid() {
+        let table = [
+            ('1', 0x1), ('2', 0x2), ('3', 0x3), ('4', 0xC),
+            ('q', 0x4), ('w', 0x5), ('e', 0x6), ('r', 0xD),
+            ('a', 0x7), ('s', 0x8), ('d', 0x9), ('f', 0xE),
+            ('z', 0xA), ('x', 0x0), ('c', 0xB), ('v', 0xF),
+        ];
+        for (c, key) in table {
+            assert_eq!(map_key(c), Some(key), "{:?}", c);
+            assert_eq!(map_key(c.to_ascii_uppercase()), Some(key), "{:?}", c.to_ascii_uppercase());
+        }
+
+        let mut keys: Vec<u8> = table.iter().map(|&(_, key)| key).collect();
+        keys.sort();
+        assert_eq!(keys, (0..16).collect::<Vec<u8>>());
+
+        for c in ['5', 't', 'g', 'b', ' ', '\n', 'é'] {
+            assert_eq!(map_key(c), None, "{:?}", c);
+        }
+    }
+}
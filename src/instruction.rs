@@ -0,0 +1,254 @@
+use crate::chip8::{Chip8, InstructionSet};
+use crate::error::Chip8Error;
+
+// one decoded opcode; x and y are register numbers, byte the kk immediate, addresses are nnn.
+// Covers CHIP-8, SCHIP and XO-CHIP alike, whether the machine accepts an opcode is up to
+// its instruction_set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    Sys(u16),                       // 0nnn, ignored
+    ClearScreen,                    // 00E0
+    Return,                         // 00EE
+    ScrollDown(u8),                 // 00Cn
+    ScrollUp(u8),                   // 00Dn
+    ScrollRight,                    // 00FB
+    ScrollLeft,                     // 00FC
+    Exit,                           // 00FD
+    LowRes,                         // 00FE
+    HighRes,                        // 00FF
+    Jump(u16),                      // 1nnn
+    Call(u16),                      // 2nnn
+    SkipEqByte { x: u8, byte: u8 }, // 3xkk
+    SkipNeByte { x: u8, byte: u8 }, // 4xkk
+    SkipEqReg { x: u8, y: u8 },     // 5xy0
+    SaveRange { x: u8, y: u8 },     // 5xy2
+    LoadRange { x: u8, y: u8 },     // 5xy3
+    SetReg { x: u8, byte: u8 },     // 6xkk
+    AddByte { x: u8, byte: u8 },    // 7xkk
+    Move { x: u8, y: u8 },          // 8xy0
+    Or { x: u8, y: u8 },            // 8xy1
+    And { x: u8, y: u8 },           // 8xy2
+    Xor { x: u8, y: u8 },           // 8xy3
+    AddReg { x: u8, y: u8 },        // 8xy4
+    Sub { x: u8, y: u8 },           // 8xy5
+    ShiftRight { x: u8, y: u8 },    // 8xy6
+    SubN { x: u8, y: u8 },          // 8xy7
+    ShiftLeft { x: u8, y: u8 },     // 8xyE
+    SkipNeReg { x: u8, y: u8 },     // 9xy0
+    SetIndex(u16),                  // Annn
+    JumpOffset(u16),                // Bnnn
+    Random { x: u8, byte: u8 },     // Cxkk
+    DrawSprite { x: u8, y: u8, n: u8 }, // Dxyn
+    SkipKeyDown { x: u8 },          // Ex9E
+    SkipKeyUp { x: u8 },            // ExA1
+    Plane(u8),                      // Fn01
+    Audio,                          // F002
+    GetDelay { x: u8 },             // Fx07
+    WaitKey { x: u8 },              // Fx0A
+    SetDelay { x: u8 },             // Fx15
+    SetSound { x: u8 },             // Fx18
+    AddIndex { x: u8 },             // Fx1E
+    Font { x: u8 },                 // Fx29
+    BigFont { x: u8 },              // Fx30
+    Bcd { x: u8 },                  // Fx33
+    Pitch { x: u8 },                // Fx3A
+    Store { x: u8 },                // Fx55
+    Load { x: u8 },                 // Fx65
+    StoreFlags { x: u8 },           // Fx75
+    LoadFlags { x: u8 },            // Fx85
+}
+
+// None for opcodes no supported interpreter defines
+pub fn decode(opcode: u16) -> Option<Instruction> {
+    let nnn: u16 = opcode & 0x0FFF;
+    let byte: u8 = (opcode & 0x00FF) as u8;
+    let x: u8 = ((opcode & 0x0F00) >> 8) as u8;
+    let y: u8 = ((opcode & 0x00F0) >> 4) as u8;
+    let n: u8 = (opcode & 0x000F) as u8;
+
+    let instruction = match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00E0 => Instruction::ClearScreen,
+            0x00EE => Instruction::Return,
+            0x00FB => Instruction::ScrollRight,
+            0x00FC => Instruction::ScrollLeft,
+            0x00FD => Instruction::Exit,
+            0x00FE => Instruction::LowRes,
+            0x00FF => Instruction::HighRes,
+            _ if opcode & 0xFFF0 == 0x00C0 => Instruction::ScrollDown(n),
+            _ if opcode & 0xFFF0 == 0x00D0 => Instruction::ScrollUp(n),
+            _ => Instruction::Sys(nnn),
+        },
+        0x1000 => Instruction::Jump(nnn),
+        0x2000 => Instruction::Call(nnn),
+        0x3000 => Instruction::SkipEqByte { x, byte },
+        0x4000 => Instruction::SkipNeByte { x, byte },
+        0x5000 => match n {
+            0x0 => Instruction::SkipEqReg { x, y },
+            0x2 => Instruction::SaveRange { x, y },
+            0x3 => Instruction::LoadRange { x, y },
+            _ => return None,
+        },
+        0x6000 => Instruction::SetReg { x, byte },
+        0x7000 => Instruction::AddByte { x, byte },
+        0x8000 => match n {
+            0x0 => Instruction::Move { x, y },
+            0x1 => Instruction::Or { x, y },
+            0x2 => Instruction::And { x, y },
+            0x3 => Instruction::Xor { x, y },
+            0x4 => Instruction::AddReg { x, y },
+            0x5 => Instruction::Sub { x, y },
+            0x6 => Instruction::ShiftRight { x, y },
+            0x7 => Instruction::SubN { x, y },
+            0xE => Instruction::ShiftLeft { x, y },
+            _ => return None,
+        },
+        0x9000 if n == 0 => Instruction::SkipNeReg { x, y },
+        0xA000 => Instruction::SetIndex(nnn),
+        0xB000 => Instruction::JumpOffset(nnn),
+        0xC000 => Instruction::Random { x, byte },
+        0xD000 => Instruction::DrawSprite { x, y, n },
+        0xE000 => match byte {
+            0x9E => Instruction::SkipKeyDown { x },
+            0xA1 => Instruction::SkipKeyUp { x },
+            _ => return None,
+        },
+        0xF000 => match byte {
+            0x01 => Instruction::Plane(x),
+            0x02 if x == 0 => Instruction::Audio,
+            0x07 => Instruction::GetDelay { x },
+            0x0A => Instruction::WaitKey { x },
+            0x15 => Instruction::SetDelay { x },
+            0x18 => Instruction::SetSound { x },
+            0x1E => Instruction::AddIndex { x },
+            0x29 => Instruction::Font { x },
+            0x30 => Instruction::BigFont { x },
+            0x33 => Instruction::Bcd { x },
+            0x3A => Instruction::Pitch { x },
+            0x55 => Instruction::Store { x },
+            0x65 => Instruction::Load { x },
+            0x75 => Instruction::StoreFlags { x },
+            0x85 => Instruction::LoadFlags { x },
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    Some(instruction)
+}
+
+// decode as a machine running `set` sees it: extension opcodes from a later set are SYS calls
+// in the 0x0 family (a no-op, like the interpreter treats them) and undefined everywhere else
+pub fn decode_for(opcode: u16, set: InstructionSet) -> Option<Instruction> {
+    match decode(opcode)? {
+        instruction if instruction.instruction_set() <= set => Some(instruction),
+        _ if opcode & 0xF000 == 0x0000 => Some(Instruction::Sys(opcode & 0x0FFF)),
+        _ => None,
+    }
+}
+
+impl Instruction {
+    // the first instruction set that defines it
+    pub fn instruction_set(&self) -> InstructionSet {
+        match self {
+            Instruction::ScrollDown(_)
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Exit
+            | Instruction::LowRes
+            | Instruction::HighRes
+            | Instruction::BigFont { .. }
+            | Instruction::StoreFlags { .. }
+            | Instruction::LoadFlags { .. } => InstructionSet::SuperChip,
+            Instruction::ScrollUp(_)
+            | Instruction::SaveRange { .. }
+            | Instruction::LoadRange { .. }
+            | Instruction::Plane(_)
+            | Instruction::Audio
+            | Instruction::Pitch { .. } => InstructionSet::XoChip,
+            _ => InstructionSet::Chip8,
+        }
+    }
+}
+
+impl Chip8 {
+    // emulate_cycle, reporting the instruction at PC that it ran, decoded for the machine's
+    // instruction_set. Opcodes that don't decode still go through emulate_cycle, so
+    // error_policy decides what they do to the machine (Lenient skips them), but step reports
+    // them as InvalidOpcode under either policy: there is no Instruction to return.
+    // A halted or paused machine runs nothing and just reports what's next.
+    pub fn step(&mut self) -> Result<Instruction, Chip8Error> {
+        let opcode = self.fetch_opcode(self.pc);
+        let instruction = decode_for(opcode, self.instruction_set);
+
+        self.emulate_cycle()?;
+        instruction.ok_or(Chip8Error::InvalidOpcode(opcode))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::ErrorPolicy;
+
+    #[test]
+    fn decodes_representative_opcodes() {
+        assert_eq!(decode(0x00E0), Some(Instruction::ClearScreen));
+        assert_eq!(decode(0x00EE), Some(Instruction::Return));
+        assert_eq!(decode(0x0123), Some(Instruction::Sys(0x123)));
+        assert_eq!(decode(0x00C4), Some(Instruction::ScrollDown(4)));
+        assert_eq!(decode(0x1ABC), Some(Instruction::Jump(0xABC)));
+        assert_eq!(decode(0x2456), Some(Instruction::Call(0x456)));
+        assert_eq!(decode(0x6A1F), Some(Instruction::SetReg { x: 0xA, byte: 0x1F }));
+        assert_eq!(decode(0x8126), Some(Instruction::ShiftRight { x: 1, y: 2 }));
+        assert_eq!(decode(0xA2F0), Some(Instruction::SetIndex(0x2F0)));
+        assert_eq!(decode(0xD125), Some(Instruction::DrawSprite { x: 1, y: 2, n: 5 }));
+        assert_eq!(decode(0xE39E), Some(Instruction::SkipKeyDown { x: 3 }));
+        assert_eq!(decode(0xF233), Some(Instruction::Bcd { x: 2 }));
+        assert_eq!(decode(0xF002), Some(Instruction::Audio));
+    }
+
+    #[test]
+    fn undefined_opcodes_do_not_decode() {
+        for opcode in [0x5121, 0x8128, 0x9121, 0xE1FF, 0xF1FF, 0xF102] {
+            assert_eq!(decode(opcode), None, "{:04X}", opcode);
+        }
+    }
+
+    #[test]
+    fn decode_for_follows_the_instruction_set() {
+        assert_eq!(decode_for(0x00FF, InstructionSet::Chip8), Some(Instruction::Sys(0x0FF)));
+        assert_eq!(decode_for(0x00FF, InstructionSet::SuperChip), Some(Instruction::HighRes));
+        assert_eq!(decode_for(0x5122, InstructionSet::SuperChip), None);
+        assert_eq!(decode_for(0x5122, InstructionSet::XoChip), Some(Instruction::SaveRange { x: 1, y: 2 }));
+    }
+
+    #[test]
+    fn step_reports_what_the_machine_ran() {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_program(&[0x00, 0xFF, 0x00, 0xFF]);
+
+        assert_eq!(chip8.step(), Ok(Instruction::Sys(0x0FF)));
+        assert!(!chip8.hires);
+
+        chip8.instruction_set = InstructionSet::SuperChip;
+        assert_eq!(chip8.step(), Ok(Instruction::HighRes));
+        assert!(chip8.hires);
+    }
+
+    #[test]
+    fn step_reports_undecoded_opcodes_under_either_policy() {
+        // 5121 is not an instruction, 6005 is LD V0, 0x05
+        for policy in [ErrorPolicy::Lenient, ErrorPolicy::Strict] {
+            let mut chip8 = Chip8::with_seed(0);
+            chip8.error_policy = policy;
+            chip8.load_program(&[0x51, 0x21, 0x60, 0x05]);
+
+            assert_eq!(chip8.step(), Err(Chip8Error::InvalidOpcode(0x5121)));
+            if policy == ErrorPolicy::Lenient {
+                assert_eq!(chip8.step(), Ok(Instruction::SetReg { x: 0, byte: 0x05 }));
+                assert_eq!(chip8.registers[0], 0x05);
+            }
+        }
+    }
+}
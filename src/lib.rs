@@ -0,0 +1,37 @@
+mod audio;
+mod builder;
+mod capabilities;
+mod chip8;
+mod debugger;
+mod disasm;
+mod error;
+mod frames;
+mod history;
+mod input;
+mod instruction;
+mod render;
+mod report;
+mod state;
+mod storage;
+mod timing;
+mod trace;
+mod variant;
+mod video;
+
+pub use audio::*;
+pub use builder::*;
+pub use capabilities::*;
+pub use chip8::*;
+pub use debugger::*;
+pub use disasm::*;
+pub use error::*;
+pub use frames::*;
+pub use history::*;
+pub use input::*;
+pub use instruction::*;
+pub use render::*;
+pub use storage::*;
+pub use timing::*;
+pub use trace::*;
+pub use variant::*;
+pub use video::*;
@@ -1,5 +1,4 @@
-mod chip8;
-use chip8::*;
+use chip8::Chip8;
 
 
 fn main() -> std::io::Result<()> {
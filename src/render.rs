@@ -0,0 +1,154 @@
+use crate::chip8::{Chip8, Color};
+
+// copy of what was on screen at one moment, see video_snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VideoSnapshot {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>             // palette indices, row-major
+}
+
+impl Chip8 {
+    pub fn video_snapshot(&self) -> VideoSnapshot {
+        VideoSnapshot {
+            width: self.screen_width(),
+            height: self.screen_height(),
+            pixels: self.video_pixels()
+        }
+    }
+
+    // FNV-1a over the resolution and every on-screen pixel, for cheap "same picture?" checks
+    pub fn video_checksum(&self) -> u32 {
+        let mut hash: u32 = 0x811C_9DC5;
+        let dimensions = [self.screen_width() as u8, self.screen_height() as u8];
+        for byte in dimensions.into_iter().chain(self.active_pixels()) {
+            hash ^= byte as u32;
+            hash = hash.wrapping_mul(0x0100_0193);
+        }
+        hash
+    }
+
+    // current screen ORed with an earlier snapshot, so sprites that flicker between two
+    // frames both show; a snapshot from another resolution is ignored
+    pub fn merged_video(&self, other: &VideoSnapshot) -> Vec<u8> {
+        let mut pixels = self.video_pixels();
+        if (other.width, other.height) == (self.screen_width(), self.screen_height()) {
+            for (pixel, &earlier) in pixels.iter_mut().zip(other.pixels.iter()) {
+                *pixel |= earlier;
+            }
+        }
+        pixels
+    }
+
+    // one character per pixel: a space for clear ones and a full block for plane 1, so plain
+    // CHIP-8 screens come out two-tone; XO-CHIP's plane 2 is a medium shade and both planes a dark one
+    pub fn render_to_string(&self) -> String {
+        self.render_with([' ', '\u{2588}', '\u{2592}', '\u{2593}'])
+    }
+
+    // `glyphs` is indexed by palette index, like the palette itself
+    pub(crate) fn render_with(&self, glyphs: [char; 4]) -> String {
+        let width = self.screen_width();
+        let mut out = String::with_capacity((width + 1) * self.screen_height());
+
+        for row in self.video_pixels().chunks(width) {
+            out.extend(row.iter().map(|&pixel| glyphs[pixel as usize & 0x3]));
+            out.push('\n');
+        }
+
+        out
+    }
+
+    // every pixel mapped through the palette, row-major
+    pub fn render_to_rgb(&self) -> Vec<Color> {
+        self.active_pixels().map(|pixel| self.palette[pixel as usize]).collect()
+    }
+
+    // scaled RGB565 framebuffer, row-major, (screen_width * scale) x (screen_height * scale) pixels
+    pub fn render_to_rgb565(&self, scale: u32, on: u16, off: u16) -> Vec<u16> {
+        let scale = scale as usize;
+        let width = self.screen_width();
+        let height = self.screen_height();
+        let out_width = width * scale;
+        let mut out = Vec::with_capacity(out_width * height * scale);
+
+        let pixels = self.video_pixels();
+        for row in 0..height {
+            let line: Vec<u16> = pixels[row * width..(row + 1) * width]
+                .iter()
+                .flat_map(|&pixel| std::iter::repeat_n(if pixel != 0 { on } else { off }, scale))
+                .collect();
+
+            for _ in 0..scale {
+                out.extend_from_slice(&line);
+            }
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::RomBuilder;
+    use crate::video::PixelBuffer;
+
+    #[test]
+    fn rgb565_is_scaled_and_mapped() {
+        let mut chip8 = Chip8::new();
+        chip8.video.set_pixel(1, 1);                 // (1, 0)
+        chip8.video.set_pixel(2 * 64 + 3, 1);        // (3, 2)
+
+        let out = chip8.render_to_rgb565(2, 0xFFFF, 0x0000);
+        assert_eq!(out.len(), 128 * 64);
+        let at = |x: usize, y: usize| out[y * 128 + x];
+        assert_eq!([at(2, 0), at(3, 0), at(2, 1), at(3, 1)], [0xFFFF; 4]);
+        assert_eq!([at(6, 4), at(7, 5)], [0xFFFF; 2]);
+        assert_eq!([at(0, 0), at(1, 1), at(4, 0), at(2, 2)], [0x0000; 4]);
+    }
+
+    #[test]
+    fn merged_video_keeps_both_snapshots() {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.video.set_pixel(5, 1);
+        let earlier = chip8.video_snapshot();
+        chip8.video.set_pixel(5, 0);
+        chip8.video.set_pixel(64 + 9, 1);
+
+        let merged = chip8.merged_video(&earlier);
+        assert_eq!(merged.len(), 64 * 32);
+        assert_eq!((merged[5], merged[64 + 9]), (1, 1));
+        assert_eq!(merged.iter().filter(|&&pixel| pixel != 0).count(), 2);
+
+        // a snapshot at another resolution is left out
+        chip8.set_hires(true);
+        chip8.video.set_pixel(64 + 9, 1);
+        assert_eq!(chip8.merged_video(&earlier).iter().filter(|&&pixel| pixel != 0).count(), 1);
+    }
+
+    #[test]
+    fn render_to_string_shows_a_cleared_then_drawn_glyph() {
+        let rom = RomBuilder::new().cls().ld(0, 0).ld_font(0).ld(1, 2).ld(2, 1).drw(1, 2, 5).halt().build();
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_program(&rom);
+        chip8.video.set_pixel(63, 1);
+        for _ in 0..6 {
+            chip8.emulate_cycle().unwrap();
+        }
+
+        let screen = chip8.render_to_string();
+        let rows: Vec<&str> = screen.lines().collect();
+        assert_eq!(rows.len(), 32);
+        assert!(rows.iter().all(|row| row.chars().count() == 64));
+
+        // glyph 0 at (2, 1)
+        let block = "\u{2588}";
+        let edge = format!("  {}", block.repeat(4));
+        let sides = format!("  {}  {}", block, block);
+        assert_eq!(rows[0].trim_end(), "");
+        assert_eq!([rows[1], rows[2], rows[3], rows[4], rows[5]].map(str::trim_end),
+            [edge.as_str(), sides.as_str(), sides.as_str(), sides.as_str(), edge.as_str()]);
+        assert!(rows[6..].iter().all(|row| row.trim_end().is_empty()));
+    }
+}
@@ -0,0 +1,277 @@
+use std::fmt::Write;
+
+use crate::chip8::{Chip8, ErrorPolicy, IndexIncrement, InstructionSet, MachineStatus, QuirkProfile};
+use crate::error::ParseError;
+use crate::video::PixelBuffer;
+
+impl Chip8 {
+    // everything worth pasting into a bug report, one item per line
+    pub fn state_report(&self) -> String {
+        let mut report = String::new();
+
+        let _ = writeln!(report, "PC=0x{:04X} I=0x{:04X} SP={}", self.pc, self.index, self.sp);
+        for (row, regs) in self.registers.chunks(8).enumerate() {
+            let line: Vec<String> = regs
+                .iter()
+                .enumerate()
+                .map(|(i, value)| format!("V{:X}={:02X}", row * 8 + i, value))
+                .collect();
+            let _ = writeln!(report, "{}", line.join(" "));
+        }
+
+        let stack: Vec<String> = self.stack[..(self.sp as usize).min(self.stack.len())]
+            .iter()
+            .map(|address| format!("0x{:04X}", address))
+            .collect();
+        if stack.is_empty() {
+            let _ = writeln!(report, "Stack: (empty)");
+        } else {
+            let _ = writeln!(report, "Stack: {}", stack.join(" "));
+        }
+
+        let _ = writeln!(report, "DT={:02X} ST={:02X}", self.delay_timer, self.sound_timer);
+        let _ = writeln!(report, "Status: {:?}", self.status);
+        let _ = writeln!(report, "Cycles: {}", self.cycles);
+        let _ = writeln!(report, "Instruction set: {:?}", self.instruction_set);
+        let _ = writeln!(report, "Error policy: {:?}", self.error_policy);
+        let _ = writeln!(report, "Quirks: {}", format_quirks(&self.quirks));
+        let _ = writeln!(report, "Screen ({}x{}):", self.screen_width(), self.screen_height());
+        report.push_str(&self.render_with(['.', '#', '#', '#']));
+
+        report
+    }
+
+    // the inverse of state_report, for loading a pasted dump: CPU registers, timers, status,
+    // cycles, instruction set, error policy, quirks and the screen (lit pixels come back as plane 1).
+    // Memory isn't in the report, so load the ROM separately. PC/I/SP, V0-VF, Stack and
+    // DT/ST are required, anything else missing keeps its power-on value.
+    pub fn from_state_report(report: &str) -> Result<Chip8, ParseError> {
+        let mut chip8 = Chip8::new();
+        let mut seen_pc = false;
+        let mut seen_registers = [false; 16];
+        let mut seen_stack = false;
+        let mut seen_timers = false;
+        let mut lines = report.lines().enumerate();
+
+        while let Some((number, line)) = lines.next() {
+            let malformed = || ParseError::Malformed { line: number + 1, text: line.to_string() };
+            let line = line.trim();
+
+            if let Some(stack) = line.strip_prefix("Stack:") {
+                let stack = stack.trim();
+                if stack != "(empty)" {
+                    for (slot, address) in stack.split_whitespace().enumerate() {
+                        *chip8.stack.get_mut(slot).ok_or_else(malformed)? = parse_hex(address).ok_or_else(malformed)?;
+                    }
+                }
+                seen_stack = true;
+            } else if let Some(status) = line.strip_prefix("Status:") {
+                chip8.status = match status.trim() {
+                    "Running" => MachineStatus::Running,
+                    "Halted" => MachineStatus::Halted,
+                    other => other.strip_prefix("WaitingForKey(")
+                        .and_then(|reg| reg.strip_suffix(')'))
+                        .and_then(|reg| reg.parse::<u8>().ok())
+                        .filter(|&reg| reg < 16)
+                        .map(MachineStatus::WaitingForKey)
+                        .ok_or_else(malformed)?,
+                };
+            } else if let Some(cycles) = line.strip_prefix("Cycles:") {
+                chip8.cycles = cycles.trim().parse().map_err(|_| malformed())?;
+            } else if let Some(set) = line.strip_prefix("Instruction set:") {
+                chip8.instruction_set = match set.trim() {
+                    "Chip8" => InstructionSet::Chip8,
+                    "SuperChip" => InstructionSet::SuperChip,
+                    "XoChip" => InstructionSet::XoChip,
+                    _ => return Err(malformed()),
+                };
+            } else if let Some(policy) = line.strip_prefix("Error policy:") {
+                chip8.error_policy = match policy.trim() {
+                    "Lenient" => ErrorPolicy::Lenient,
+                    "Strict" => ErrorPolicy::Strict,
+                    _ => return Err(malformed()),
+                };
+            } else if let Some(quirks) = line.strip_prefix("Quirks:") {
+                chip8.quirks = parse_quirks(quirks).ok_or_else(malformed)?;
+            } else if let Some(size) = line.strip_prefix("Screen (").and_then(|size| size.strip_suffix("):")) {
+                let (width, height) = size.split_once('x').ok_or_else(malformed)?;
+                chip8.set_hires(width == "128" && height == "64");
+                if (width.parse(), height.parse()) != (Ok(chip8.screen_width()), Ok(chip8.screen_height())) {
+                    return Err(malformed());
+                }
+
+                for row in 0..chip8.screen_height() {
+                    let Some((number, pixels)) = lines.next() else { break };
+                    if pixels.chars().count() != chip8.screen_width() {
+                        return Err(ParseError::Malformed { line: number + 1, text: pixels.to_string() });
+                    }
+                    for (col, pixel) in pixels.chars().enumerate() {
+                        let value = (pixel == '#') as u8;
+                        chip8.video.set_pixel(row * chip8.screen_width() + col, value);
+                    }
+                }
+            } else {
+                // the KEY=value lines: PC/I/SP, the registers, DT/ST
+                for item in line.split_whitespace() {
+                    let (key, value) = item.split_once('=').ok_or_else(malformed)?;
+                    match key {
+                        "PC" => {
+                            chip8.pc = parse_hex(value).ok_or_else(malformed)?;
+                            seen_pc = true;
+                        }
+                        "I" => chip8.index = parse_hex(value).ok_or_else(malformed)?,
+                        "SP" => {
+                            let sp: u8 = value.parse().map_err(|_| malformed())?;
+                            if sp as usize > chip8.stack.len() {
+                                return Err(malformed());
+                            }
+                            chip8.sp = sp;
+                        }
+                        "DT" | "ST" => {
+                            let timer = u8::from_str_radix(value, 16).map_err(|_| malformed())?;
+                            if key == "DT" { chip8.delay_timer = timer } else { chip8.sound_timer = timer }
+                            seen_timers = true;
+                        }
+                        _ => {
+                            let reg = key.strip_prefix('V')
+                                .and_then(|reg| u8::from_str_radix(reg, 16).ok())
+                                .filter(|&reg| reg < 16)
+                                .ok_or_else(malformed)? as usize;
+                            chip8.registers[reg] = u8::from_str_radix(value, 16).map_err(|_| malformed())?;
+                            seen_registers[reg] = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        let missing = [
+            (seen_pc, "PC/I/SP line"),
+            (seen_registers.iter().all(|&seen| seen), "V0-VF"),
+            (seen_stack, "Stack line"),
+            (seen_timers, "DT/ST line"),
+        ];
+        match missing.iter().find(|(seen, _)| !seen) {
+            Some(&(_, item)) => Err(ParseError::Missing(item)),
+            None => Ok(chip8),
+        }
+    }
+}
+
+// "display_wait=false clip_sprites=true ..." with every field, so the line reads back exactly
+fn format_quirks(quirks: &QuirkProfile) -> String {
+    format!(
+        "display_wait={} clip_sprites={} shift_vy={} load_store_increment={:?} jump_vx={} load_store_clears_vf={} add_i_overflow_flag={}",
+        quirks.display_wait,
+        quirks.clip_sprites,
+        quirks.shift_vy,
+        quirks.load_store_increment,
+        quirks.jump_vx,
+        quirks.load_store_clears_vf,
+        quirks.add_i_overflow_flag
+    )
+}
+
+// fields left out keep their default
+fn parse_quirks(text: &str) -> Option<QuirkProfile> {
+    let mut quirks = QuirkProfile::default();
+
+    for item in text.split_whitespace() {
+        let (key, value) = item.split_once('=')?;
+        if key == "load_store_increment" {
+            quirks.load_store_increment = match value {
+                "XPlusOne" => IndexIncrement::XPlusOne,
+                "X" => IndexIncrement::X,
+                "None" => IndexIncrement::None,
+                _ => return None,
+            };
+            continue;
+        }
+
+        let value: bool = value.parse().ok()?;
+        match key {
+            "display_wait" => quirks.display_wait = value,
+            "clip_sprites" => quirks.clip_sprites = value,
+            "shift_vy" => quirks.shift_vy = value,
+            "jump_vx" => quirks.jump_vx = value,
+            "load_store_clears_vf" => quirks.load_store_clears_vf = value,
+            "add_i_overflow_flag" => quirks.add_i_overflow_flag = value,
+            _ => return None,
+        }
+    }
+
+    Some(quirks)
+}
+
+// "0x0200" or bare "0200"
+fn parse_hex(text: &str) -> Option<u16> {
+    u16::from_str_radix(text.strip_prefix("0x").unwrap_or(text), 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::RomBuilder;
+
+    fn ran_program() -> Chip8 {
+        let rom = RomBuilder::new().ld(0, 0x12).ld(0xA, 0xCD).ld_font(0).drw(1, 1, 5).halt().build();
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_program(&rom);
+        chip8.run_until_breakpoint(4);
+        chip8
+    }
+
+    #[test]
+    fn report_has_registers_quirks_and_screen() {
+        let mut chip8 = ran_program();
+        chip8.quirks.jump_vx = true;
+        let report = chip8.state_report();
+
+        assert!(report.contains("PC=0x0208 I=0x005A SP=0"), "{}", report);
+        assert!(report.contains("V0=12 V1=00"), "{}", report);
+        assert!(report.contains("VA=CD"), "{}", report);
+        assert!(report.contains("Quirks: display_wait=false clip_sprites=true shift_vy=false \
+            load_store_increment=XPlusOne jump_vx=true"), "{}", report);
+        assert!(report.contains("Screen (64x32):\n"), "{}", report);
+        // glyph 2 at (0, 0): "####" then "...#"
+        assert!(report.contains("\n####......"), "{}", report);
+        assert!(report.contains("\n...#......"), "{}", report);
+    }
+
+    #[test]
+    fn quirks_line_reads_back() {
+        let mut chip8 = ran_program();
+        chip8.quirks = QuirkProfile {
+            shift_vy: true,
+            load_store_increment: IndexIncrement::None,
+            add_i_overflow_flag: true,
+            ..QuirkProfile::default()
+        };
+
+        let parsed = Chip8::from_state_report(&chip8.state_report()).unwrap();
+        assert_eq!(parsed.quirks, chip8.quirks);
+    }
+
+    #[test]
+    fn bad_quirks_line_is_malformed() {
+        let report = ran_program().state_report().replace("jump_vx=false", "jump_vx=maybe");
+        assert!(matches!(Chip8::from_state_report(&report), Err(ParseError::Malformed { .. })));
+    }
+
+    #[test]
+    fn cpu_state_round_trips() {
+        let rom = RomBuilder::new().call(0x204).halt().ld_i(0x345).ld(3, 0x7F).ld(0xF, 0x01).ld_dt(3).ld_st(3).build();
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_program(&rom);
+        chip8.run_until_breakpoint(6);
+
+        let parsed = Chip8::from_state_report(&chip8.state_report()).unwrap();
+        assert_eq!(parsed.registers, chip8.registers);
+        assert_eq!(parsed.index, 0x345);
+        assert_eq!(parsed.pc, chip8.pc);
+        assert_eq!(parsed.sp, 1);
+        assert_eq!(parsed.stack[0], 0x202);
+        assert_eq!(parsed.stack, chip8.stack);
+        assert_eq!((parsed.delay_timer, parsed.sound_timer), (0x7F, 0x7F));
+    }
+}
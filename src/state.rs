@@ -0,0 +1,270 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+
+use crate::audio::AudioPattern;
+use crate::chip8::{Chip8, MachineStatus};
+use crate::error::Chip8Error;
+use crate::video::{PixelBuffer, VIDEO_SIZE};
+
+const STATE_MAGIC: &[u8; 4] = b"C8ST";
+const STATE_VERSION: u8 = 4;
+
+// cursor over a save state, every read fails the same way once the bytes run out
+struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize
+}
+
+impl<'a> StateReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Chip8Error> {
+        let bytes = self.data.get(self.pos..self.pos + len).ok_or(Chip8Error::InvalidSaveState)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Chip8Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, Chip8Error> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(Chip8Error::InvalidSaveState),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, Chip8Error> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, Chip8Error> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, Chip8Error> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+impl Chip8 {
+    // everything a running program can observe, in a small versioned binary format: memory,
+    // CPU, timers, screen, keypad, audio pattern, run status (an Fx0A wait included), pause,
+    // the position inside the current frame and the RNG (seed and how far into its stream
+    // it is). Configuration, breakpoints and history are not saved.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(STATE_MAGIC.len() + self.memory.len() + VIDEO_SIZE + 128);
+        out.extend_from_slice(STATE_MAGIC);
+        out.push(STATE_VERSION);
+
+        out.extend_from_slice(&self.memory);
+        out.extend_from_slice(&self.registers);
+        out.extend_from_slice(&self.index.to_le_bytes());
+        out.extend_from_slice(&self.pc.to_le_bytes());
+        for slot in self.stack {
+            out.extend_from_slice(&slot.to_le_bytes());
+        }
+        out.push(self.sp);
+        out.push(self.delay_timer);
+        out.push(self.sound_timer);
+        out.push(self.opcode as u8);
+        out.push((self.opcode >> 8) as u8);
+
+        let (status, waiting_reg) = match self.status {
+            MachineStatus::Running => (0, 0),
+            MachineStatus::Halted => (1, 0),
+            MachineStatus::WaitingForKey(reg) => (2, reg),
+        };
+        out.extend_from_slice(&[status, waiting_reg, self.paused as u8]);
+
+        out.push(self.hires as u8);
+        out.push(self.selected_planes);
+        out.extend((0..VIDEO_SIZE).map(|pixel| self.video.get_pixel(pixel)));
+        out.extend(self.keypad.iter().map(|&down| down as u8));
+        out.extend(self.previous_keypad.iter().map(|&down| down as u8));
+        out.extend_from_slice(&self.rpl_flags);
+        out.extend_from_slice(&self.audio_pattern.bits);
+        out.push(self.audio_pattern.pitch);
+
+        out.extend_from_slice(&self.cycles.to_le_bytes());
+        out.extend_from_slice(&(self.rom_len as u32).to_le_bytes());
+        out.extend_from_slice(&self.frame_cycle.to_le_bytes());
+        out.extend_from_slice(&self.frame_machine_cycles.to_le_bytes());
+        out.extend_from_slice(&self.halted_frames.to_le_bytes());
+        out.extend_from_slice(&self.timer_accumulator.to_le_bytes());
+        out.push(self.vblank_wait as u8);
+        out.push(self.drew as u8);
+        out.extend_from_slice(&self.rng_seed.to_le_bytes());
+        out.extend_from_slice(&self.rng.get_word_pos().to_le_bytes());
+
+        out
+    }
+
+    // restores a save_state; on error, nothing has been changed
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        let mut reader = StateReader { data, pos: 0 };
+        if reader.take(STATE_MAGIC.len())? != STATE_MAGIC || reader.u8()? != STATE_VERSION {
+            return Err(Chip8Error::InvalidSaveState);
+        }
+
+        // parse into a copy first so a truncated state can't leave the machine half loaded
+        let memory: [u8; 4096] = reader.take(4096)?.try_into().unwrap();
+        let registers: [u8; 16] = reader.take(16)?.try_into().unwrap();
+        let index = reader.u16()?;
+        let pc = reader.u16()?;
+        let mut stack = [0u16; 16];
+        for slot in stack.iter_mut() {
+            *slot = reader.u16()?;
+        }
+        let sp = reader.u8()?;
+        let delay_timer = reader.u8()?;
+        let sound_timer = reader.u8()?;
+        let opcode = reader.u8()? as u16 | (reader.u8()? as u16) << 8;
+
+        let status = match (reader.u8()?, reader.u8()?) {
+            (0, _) => MachineStatus::Running,
+            (1, _) => MachineStatus::Halted,
+            (2, reg) if reg < 16 => MachineStatus::WaitingForKey(reg),
+            _ => return Err(Chip8Error::InvalidSaveState),
+        };
+        let paused = reader.bool()?;
+
+        let hires = reader.bool()?;
+        let selected_planes = reader.u8()?;
+        let video = reader.take(VIDEO_SIZE)?;
+        let mut keypad = [false; 16];
+        for key in keypad.iter_mut() {
+            *key = reader.bool()?;
+        }
+        let mut previous_keypad = [false; 16];
+        for key in previous_keypad.iter_mut() {
+            *key = reader.bool()?;
+        }
+        let rpl_flags: [u8; 16] = reader.take(16)?.try_into().unwrap();
+        let audio_pattern = AudioPattern { bits: reader.take(16)?.try_into().unwrap(), pitch: reader.u8()? };
+
+        let cycles = reader.u64()?;
+        let rom_len = reader.u32()? as usize;
+        let frame_cycle = reader.u32()?;
+        let frame_machine_cycles = reader.u32()?;
+        let halted_frames = reader.u32()?;
+        let timer_accumulator = f64::from_bits(reader.u64()?);
+        let vblank_wait = reader.bool()?;
+        let drew = reader.bool()?;
+        let rng_seed = reader.u64()?;
+        let rng_position = u128::from_le_bytes(reader.take(16)?.try_into().unwrap());
+
+        if sp as usize > stack.len() || reader.pos != data.len() {
+            return Err(Chip8Error::InvalidSaveState);
+        }
+
+        self.memory = memory;
+        self.registers = registers;
+        self.index = index;
+        self.pc = pc;
+        self.stack = stack;
+        self.sp = sp;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.opcode = opcode;
+        self.status = status;
+        self.paused = paused;
+        self.hires = hires;
+        self.selected_planes = selected_planes;
+        for (pixel, &value) in video.iter().enumerate() {
+            self.video.set_pixel(pixel, value);
+        }
+        self.keypad = keypad;
+        self.previous_keypad = previous_keypad;
+        self.rpl_flags = rpl_flags;
+        self.audio_pattern = audio_pattern;
+        self.cycles = cycles;
+        self.rom_len = rom_len;
+        self.frame_cycle = frame_cycle;
+        self.frame_machine_cycles = frame_machine_cycles;
+        self.halted_frames = halted_frames;
+        self.timer_accumulator = timer_accumulator;
+        self.vblank_wait = vblank_wait;
+        self.drew = drew;
+        self.rng_seed = rng_seed;
+        self.rng = ChaCha12Rng::seed_from_u64(rng_seed);
+        self.rng.set_word_pos(rng_position);
+
+        self.pending_input = None;
+        self.stopped_at = None;
+        self.call_returns = self.stack[..self.sp as usize].to_vec(); // the saved frames count as called
+        self.events.clear();
+        self.history.clear();
+
+        Ok(())
+    }
+
+    // save_state into the storage backend under `slot`
+    pub fn store_state(&mut self, slot: &str) -> std::io::Result<()> {
+        let state = self.save_state();
+        self.storage.store(slot, &state)
+    }
+
+    // load_state from the storage backend; Ok(false) if nothing is stored under `slot`
+    pub fn restore_state(&mut self, slot: &str) -> Result<bool, Chip8Error> {
+        match self.storage.load(slot) {
+            Some(state) => self.load_state(&state).map(|_| true),
+            None => Ok(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::RomBuilder;
+    use crate::debugger::StopReason;
+
+    #[test]
+    fn key_wait_survives_pause_and_a_save_state() {
+        let mut chip8 = Chip8::with_seed(0);
+        let rom = RomBuilder::new().ld_key(3).add(4, 1).halt().build();
+        chip8.memory[0x200..0x200 + rom.len()].copy_from_slice(&rom);
+        chip8.run_until_breakpoint(3);
+        assert_eq!((chip8.status, chip8.pc), (MachineStatus::WaitingForKey(3), 0x200));
+        chip8.pause();
+        let state = chip8.save_state();
+
+        let mut restored = Chip8::with_seed(1);
+        restored.load_state(&state).unwrap();
+        assert_eq!(restored.status, MachineStatus::WaitingForKey(3));
+        assert_eq!(restored.run_until_breakpoint(3).reason, StopReason::Paused);
+
+        restored.resume();
+        restored.run_until_breakpoint(3);
+        assert_eq!((restored.status, restored.pc), (MachineStatus::WaitingForKey(3), 0x200));
+        restored.press_key(9);
+        restored.run_until_breakpoint(2);
+        assert_eq!(restored.status, MachineStatus::Running);
+        assert_eq!((restored.registers[3], restored.registers[4], restored.pc), (9, 1, 0x204));
+    }
+
+    #[test]
+    fn restore_brings_back_the_snapshot_and_replays_the_same_run() {
+        // RND, a draw, a timer and a store every pass round the loop
+        let rom = RomBuilder::new().rnd(0, 0xFF).ld_font(0).drw(1, 2, 5).add(1, 3).ld_dt(0)
+            .ld_i(0x300).ld_bcd(0).jp(0x200).build();
+        let mut chip8 = Chip8::with_seed(7);
+        chip8.load_rom_from_bytes(&rom).unwrap();
+        chip8.run_until_breakpoint(37);
+
+        let snapshot = chip8.save_state();
+        let (registers, video) = (chip8.registers, chip8.video_pixels());
+        chip8.run_until_breakpoint(50);
+        let after = chip8.save_state();
+        assert_ne!(after, snapshot);
+
+        chip8.load_state(&snapshot).unwrap();
+        assert_eq!(chip8.save_state(), snapshot);
+        assert_eq!((chip8.registers, chip8.video_pixels()), (registers, video));
+
+        // the RND calls after the restore repeat the ones after the snapshot
+        chip8.run_until_breakpoint(50);
+        assert_eq!(chip8.save_state(), after);
+    }
+}
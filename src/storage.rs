@@ -0,0 +1,122 @@
+use std::{collections::HashMap, fs, io, path::PathBuf};
+
+// where RPL flags and save states live between runs
+pub trait StorageBackend {
+    fn load(&self, key: &str) -> Option<Vec<u8>>;
+    fn store(&mut self, key: &str, data: &[u8]) -> io::Result<()>;
+}
+
+// remembers nothing
+#[derive(Default)]
+pub struct NullStorage;
+
+impl StorageBackend for NullStorage {
+    fn load(&self, _key: &str) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn store(&mut self, _key: &str, _data: &[u8]) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// one file per key inside a directory
+pub struct FileStorage {
+    dir: PathBuf
+}
+
+impl FileStorage {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        FileStorage { dir: dir.into() }
+    }
+}
+
+impl StorageBackend for FileStorage {
+    fn load(&self, key: &str) -> Option<Vec<u8>> {
+        fs::read(self.dir.join(key)).ok()
+    }
+
+    fn store(&mut self, key: &str, data: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::write(self.dir.join(key), data)
+    }
+}
+
+// keeps everything in a map, handy for tests and for hosts without a filesystem
+#[derive(Default)]
+pub struct MemoryStorage {
+    entries: HashMap<String, Vec<u8>>
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl StorageBackend for MemoryStorage {
+    fn load(&self, key: &str) -> Option<Vec<u8>> {
+        self.entries.get(key).cloned()
+    }
+
+    fn store(&mut self, key: &str, data: &[u8]) -> io::Result<()> {
+        self.entries.insert(key.to_string(), data.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::{Chip8, Event, InstructionSet};
+
+    // a disk that is always full
+    struct FailingStorage;
+
+    impl StorageBackend for FailingStorage {
+        fn load(&self, _key: &str) -> Option<Vec<u8>> {
+            None
+        }
+
+        fn store(&mut self, _key: &str, _data: &[u8]) -> io::Result<()> {
+            Err(io::Error::other("disk full"))
+        }
+    }
+
+    #[test]
+    fn rpl_flags_survive_a_hard_reset() {
+        let mut chip8 = Chip8::new();
+        chip8.instruction_set = InstructionSet::SuperChip;
+        chip8.set_storage_backend(Box::new(MemoryStorage::new()));
+        // LD V0, 0x12 / LD V1, 0x34 / LD V2, 0x56 / LD R, V2
+        chip8.memory[0x200..0x208].copy_from_slice(&[0x60, 0x12, 0x61, 0x34, 0x62, 0x56, 0xF2, 0x75]);
+        for _ in 0..4 {
+            chip8.emulate_cycle().unwrap();
+        }
+
+        chip8.hard_reset();
+        assert_eq!(chip8.rpl_flags, [0; 16]);
+        assert_eq!(chip8.registers, [0; 16]);
+
+        // LD V2, R
+        chip8.memory[0x200..0x202].copy_from_slice(&[0xF2, 0x85]);
+        chip8.emulate_cycle().unwrap();
+        assert_eq!(chip8.registers[..3], [0x12, 0x34, 0x56]);
+    }
+
+    #[test]
+    fn store_failures_reach_the_caller() {
+        let mut chip8 = Chip8::new();
+        chip8.instruction_set = InstructionSet::SuperChip;
+        chip8.set_storage_backend(Box::new(FailingStorage));
+        assert_eq!(chip8.store_state("slot").unwrap_err().to_string(), "disk full");
+
+        // LD V0, 0x12 / LD R, V0: the flags still hold for this run
+        chip8.memory[0x200..0x204].copy_from_slice(&[0x60, 0x12, 0xF0, 0x75]);
+        for _ in 0..2 {
+            chip8.emulate_cycle().unwrap();
+        }
+        assert_eq!(chip8.rpl_flags[0], 0x12);
+        assert_eq!(chip8.events, [Event::StorageWriteFailed { pc: 0x202 }]);
+    }
+}
@@ -0,0 +1,137 @@
+use std::{cell::Cell, rc::Rc, time::{Duration, Instant}};
+
+// where the run loop gets "now" from, so pacing can be driven by a fake clock
+pub trait TimeSource {
+    fn now(&self) -> Duration;      // time elapsed since some fixed origin
+}
+
+pub struct SystemTimeSource {
+    start: Instant
+}
+
+impl SystemTimeSource {
+    pub fn new() -> Self {
+        SystemTimeSource { start: Instant::now() }
+    }
+}
+
+impl Default for SystemTimeSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeSource for SystemTimeSource {
+    fn now(&self) -> Duration {
+        self.start.elapsed()
+    }
+}
+
+// clones share the same clock, so a test can keep one and hand the other to the machine
+#[derive(Clone, Default)]
+pub struct MockTimeSource {
+    now: Rc<Cell<Duration>>
+}
+
+impl MockTimeSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now.set(self.now.get() + by);
+    }
+
+    pub fn set(&self, to: Duration) {
+        self.now.set(to);
+    }
+}
+
+impl TimeSource for MockTimeSource {
+    fn now(&self) -> Duration {
+        self.now.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    #[test]
+    fn frames_and_ticks_follow_the_mock_clock() {
+        let clock = MockTimeSource::new();
+        let mut chip8 = Chip8::new();
+        // ADD V0, 1 / JP 0x200
+        chip8.memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]);
+        chip8.set_time_source(Box::new(clock.clone()));
+        chip8.set_delay_timer(10);
+
+        // the first frame is due straight away, the next one 1/60s later
+        assert_eq!(chip8.run_due_frames(), Ok(1));
+        clock.advance(Duration::from_millis(10));
+        assert_eq!(chip8.run_due_frames(), Ok(0));
+        clock.advance(Duration::from_millis(7));
+        assert_eq!(chip8.run_due_frames(), Ok(1));
+        assert_eq!(chip8.delay_timer, 8);
+
+        clock.advance(Duration::from_millis(50));
+        assert_eq!(chip8.run_due_frames(), Ok(3));
+        assert_eq!(chip8.delay_timer, 5);
+        // five frames of ten instructions, every other one an ADD
+        assert_eq!(chip8.registers[0], 25);
+    }
+
+    #[test]
+    fn measured_hz_converges_on_the_simulated_rate() {
+        let clock = MockTimeSource::new();
+        let mut chip8 = Chip8::new();
+        chip8.memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]); // ADD V0, 1 / JP 0x200
+        chip8.set_time_source(Box::new(clock.clone()));
+        let frames = |chip8: &mut Chip8, count: u32, every: Duration| {
+            for _ in 0..count {
+                clock.advance(every);
+                chip8.run_frame();
+            }
+        };
+
+        assert_eq!(chip8.measured_hz(), 0.0);
+        // 10 instructions every 20ms
+        frames(&mut chip8, 30, Duration::from_millis(20));
+        assert!((chip8.measured_hz() - 500.0).abs() < 1e-6, "{}", chip8.measured_hz());
+
+        // once the window has filled with 10ms frames the old cadence is forgotten
+        frames(&mut chip8, 60, Duration::from_millis(10));
+        assert!((chip8.measured_hz() - 1000.0).abs() < 1e-6, "{}", chip8.measured_hz());
+    }
+
+    #[test]
+    fn timers_tick_sixty_times_a_second_at_59_94_hz() {
+        let clock = MockTimeSource::new();
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.memory[0x200..0x204].copy_from_slice(&[0x70, 0x01, 0x12, 0x00]); // ADD V0, 1 / JP 0x200
+        chip8.set_time_source(Box::new(clock.clone()));
+        chip8.frame_rate = 59.94;
+        chip8.set_delay_timer(100);
+
+        // frames fall due at k / 59.94s; 60 of them (k = 0..=59) within the first second
+        let mut frames = chip8.run_due_frames().unwrap();
+        for _ in 0..100 {
+            clock.advance(Duration::from_millis(10));
+            frames += chip8.run_due_frames().unwrap();
+        }
+        assert_eq!(frames, 60);
+        assert_eq!(chip8.delay_timer, 40);
+
+        // over 1000 more frames (16.68s) the carried fraction adds up to one extra tick
+        let mut ticks = 0;
+        for _ in 0..10 {
+            chip8.set_delay_timer(255);
+            for _ in 0..100 {
+                chip8.run_frame();
+            }
+            ticks += 255 - chip8.delay_timer as u32;
+        }
+        assert_eq!(ticks, 1001);
+    }
+}
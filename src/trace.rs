@@ -0,0 +1,79 @@
+use std::fmt;
+
+use crate::chip8::{Chip8, MachineStatus};
+use crate::disasm::disassemble_opcode;
+use crate::history::{Capture, Change};
+
+// one executed instruction and the CPU state it changed; PC is left out (the next line's pc
+// shows it) and so are memory and video, which would swamp the trace, and the bookkeeping
+// (opcode counts, RNG position) that only step_back needs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceLine {
+    pub pc: u16,
+    pub opcode: u16,
+    pub mnemonic: String,
+    pub changes: Vec<Change>
+}
+
+// "0x0202 7105 ADD V1, 0x05 ; V1=0A VF=00", stable enough to commit as a golden file
+impl fmt::Display for TraceLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#06X} {:04X} {}", self.pc, self.opcode, self.mnemonic)?;
+
+        for (i, change) in self.changes.iter().enumerate() {
+            f.write_str(if i == 0 { " ; " } else { " " })?;
+            match *change {
+                Change::Register { reg, new, .. } => write!(f, "V{:X}={:02X}", reg, new)?,
+                Change::Index { new, .. } => write!(f, "I={:#06X}", new)?,
+                Change::Sp { new, .. } => write!(f, "SP={}", new)?,
+                Change::Stack { slot, new, .. } => write!(f, "S{:X}={:#06X}", slot, new)?,
+                Change::DelayTimer { new, .. } => write!(f, "DT={:02X}", new)?,
+                Change::SoundTimer { new, .. } => write!(f, "ST={:02X}", new)?,
+                Change::Planes { new, .. } => write!(f, "PLANES={}", new)?,
+                Change::Hires { new, .. } => write!(f, "HIRES={}", new)?,
+                Change::Status { new, .. } => write!(f, "STATUS={:?}", new)?,
+                Change::Audio { old, new } if old.pitch != new.pitch => write!(f, "PITCH={}", new.pitch)?,
+                Change::Audio { .. } => f.write_str("PATTERN")?,
+                Change::RplFlag { slot, new, .. } => write!(f, "R{:X}={:02X}", slot, new)?,
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// runs `rom` on a fresh machine seeded with `seed` for up to `cycles` instructions, one line
+// each, stopping early once the machine halts. Instructions only, no frames, so the timers
+// never tick and input is never polled: the same arguments always give the same trace.
+pub fn generate_trace(rom: &[u8], cycles: u64, seed: u64) -> Vec<TraceLine> {
+    let mut chip8 = Chip8::with_seed(seed);
+    chip8.load_program(rom);
+
+    let mut trace = Vec::new();
+    for _ in 0..cycles {
+        if chip8.status == MachineStatus::Halted {
+            break;
+        }
+
+        let pc = chip8.pc;
+        let opcode = chip8.fetch_opcode(pc);
+        let before = Capture::take(&chip8);
+        // under the default lenient policy an error halts the machine, which the trace shows
+        let _ = chip8.emulate_cycle();
+
+        let changes = before.diff(&chip8).changes.into_iter().filter(shown).collect();
+        trace.push(TraceLine { pc, opcode, mnemonic: disassemble_opcode(opcode), changes });
+    }
+
+    trace
+}
+
+fn shown(change: &Change) -> bool {
+    !matches!(
+        change,
+        Change::Pc { .. } | Change::Memory { .. } | Change::Video { .. } | Change::OpcodeCount { .. } | Change::RngPosition { .. }
+            | Change::FrameCycle { .. } | Change::FrameMachineCycles { .. } | Change::TimerAccumulator { .. }
+            | Change::VblankWait { .. } | Change::Drew { .. }
+    )
+}
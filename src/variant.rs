@@ -0,0 +1,155 @@
+use crate::chip8::{Chip8, IndexIncrement, InstructionSet, QuirkProfile};
+
+// the interpreters this emulator can pass for, each a full instruction set + quirk combination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Variant {
+    Chip8,                          // COSMAC VIP
+    SuperChip1_0,
+    SuperChip1_1,
+    XoChip,
+}
+
+impl Chip8Variant {
+    pub fn instruction_set(self) -> InstructionSet {
+        match self {
+            Chip8Variant::Chip8 => InstructionSet::Chip8,
+            Chip8Variant::SuperChip1_0 | Chip8Variant::SuperChip1_1 => InstructionSet::SuperChip,
+            Chip8Variant::XoChip => InstructionSet::XoChip,
+        }
+    }
+
+    pub fn quirks(self) -> QuirkProfile {
+        let schip = QuirkProfile {
+            display_wait: false,
+            clip_sprites: true,
+            shift_vy: false,
+            load_store_increment: IndexIncrement::None,
+            jump_vx: true,
+            load_store_clears_vf: false,
+            add_i_overflow_flag: false,
+        };
+
+        match self {
+            Chip8Variant::Chip8 => QuirkProfile {
+                display_wait: true,
+                shift_vy: true,
+                load_store_clears_vf: true,
+                ..QuirkProfile::default()
+            },
+            Chip8Variant::SuperChip1_0 => QuirkProfile { load_store_increment: IndexIncrement::X, ..schip },
+            Chip8Variant::SuperChip1_1 => schip,
+            Chip8Variant::XoChip => QuirkProfile {
+                display_wait: false,
+                clip_sprites: false,
+                shift_vy: true,
+                load_store_increment: IndexIncrement::XPlusOne,
+                jump_vx: false,
+                load_store_clears_vf: false,
+                add_i_overflow_flag: false,
+            },
+        }
+    }
+}
+
+impl Chip8 {
+    // best match for the current settings. Only the instruction set and, to tell SCHIP 1.0
+    // from 1.1, load_store_increment count; the other quirks are ignored, so a preset with a
+    // quirk or two changed still reports that preset.
+    pub fn variant(&self) -> Chip8Variant {
+        match self.instruction_set {
+            InstructionSet::Chip8 => Chip8Variant::Chip8,
+            InstructionSet::SuperChip if self.quirks.load_store_increment == IndexIncrement::X => Chip8Variant::SuperChip1_0,
+            InstructionSet::SuperChip => Chip8Variant::SuperChip1_1,
+            InstructionSet::XoChip => Chip8Variant::XoChip,
+        }
+    }
+
+    // instruction set and quirks in one go, and back to low resolution (which clears the screen)
+    pub fn set_variant(&mut self, variant: Chip8Variant) {
+        self.instruction_set = variant.instruction_set();
+        self.quirks = variant.quirks();
+        self.set_hires(false);
+    }
+}
+
+// runs the ROM on a fresh machine per profile (same seed each time) and pairs each profile
+// with the video checksum after `frames` frames; profiles that disagree are the ones that matter
+pub fn compare_quirks(rom: &[u8], frames: u32, profiles: &[QuirkProfile]) -> Vec<(QuirkProfile, u32)> {
+    profiles
+        .iter()
+        .map(|&profile| {
+            let mut chip8 = Chip8::with_seed(0);
+            chip8.quirks = profile;
+            chip8.load_program(rom);
+            chip8.run(frames);
+            (profile, chip8.video_checksum())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::RomBuilder;
+
+    #[test]
+    fn set_variant_applies_instruction_set_and_quirks() {
+        let variants = [
+            Chip8Variant::Chip8,
+            Chip8Variant::SuperChip1_0,
+            Chip8Variant::SuperChip1_1,
+            Chip8Variant::XoChip,
+        ];
+
+        for variant in variants {
+            let mut chip8 = Chip8::with_seed(0);
+            chip8.hires = true;
+            chip8.set_variant(variant);
+
+            assert_eq!(chip8.instruction_set, variant.instruction_set());
+            assert_eq!(chip8.quirks, variant.quirks());
+            assert_eq!(chip8.variant(), variant);
+            assert!(!chip8.hires);
+        }
+
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_variant(Chip8Variant::Chip8);
+        assert!(chip8.quirks.display_wait);
+        assert!(chip8.quirks.load_store_clears_vf);
+
+        // only the instruction set and load_store_increment decide
+        chip8.quirks.display_wait = false;
+        chip8.quirks.clip_sprites = false;
+        assert_eq!(chip8.variant(), Chip8Variant::Chip8);
+        chip8.instruction_set = InstructionSet::SuperChip;
+        chip8.quirks.load_store_increment = IndexIncrement::X;
+        assert_eq!(chip8.variant(), Chip8Variant::SuperChip1_0);
+    }
+
+    #[test]
+    fn variant_quirks() {
+        assert!(Chip8Variant::Chip8.quirks().shift_vy);
+        assert!(!Chip8Variant::SuperChip1_1.quirks().shift_vy);
+        assert!(Chip8Variant::SuperChip1_1.quirks().jump_vx);
+        assert_eq!(Chip8Variant::SuperChip1_0.quirks().load_store_increment, IndexIncrement::X);
+        assert_eq!(Chip8Variant::SuperChip1_1.quirks().load_store_increment, IndexIncrement::None);
+        assert!(!Chip8Variant::XoChip.quirks().clip_sprites);
+    }
+
+    #[test]
+    fn default_quirks_shift_vx_in_place() {
+        assert!(!QuirkProfile::default().shift_vy);
+    }
+
+    #[test]
+    fn compare_quirks_tells_shift_profiles_apart() {
+        // draws a pixel at x = V0 after SHR V0, V1: 0 when shifting in place, 4 when shifting Vy
+        let rom = RomBuilder::new().ld(0, 1).ld(1, 8).shr(0, 1).ld_i(0x20C).drw(0, 2, 1).halt().data(&[0x80, 0x00]).build();
+        let in_place = QuirkProfile::default();
+        let profiles = [in_place, QuirkProfile { shift_vy: true, ..in_place }, QuirkProfile { jump_vx: true, ..in_place }];
+
+        let checksums: Vec<u32> = compare_quirks(&rom, 1, &profiles).into_iter().map(|(_, checksum)| checksum).collect();
+        assert_ne!(checksums[0], checksums[1]);
+        assert_eq!(checksums[0], checksums[2]);
+    }
+}
@@ -0,0 +1,135 @@
+// Storage for the screen. By default one byte per pixel (bit 0 = plane 1, bit 1 = plane 2);
+// with the `packed-video` feature one bit per pixel per plane, a quarter of the size.
+// Everything outside the DRW fast path goes through PixelBuffer, so both behave the same.
+
+pub(crate) const VIDEO_SIZE: usize = 128 * 64;
+
+// per-pixel access by row-major index, values are plane bitmasks (0-3)
+pub trait PixelBuffer {
+    fn get_pixel(&self, index: usize) -> u8;
+    fn set_pixel(&mut self, index: usize, value: u8);
+
+    // XORs `value` into the pixel and returns the planes that were lit before and got cleared
+    fn xor_pixel(&mut self, index: usize, value: u8) -> u8 {
+        let old = self.get_pixel(index);
+        self.set_pixel(index, old ^ value);
+        old & value
+    }
+}
+
+impl PixelBuffer for [u8; VIDEO_SIZE] {
+    fn get_pixel(&self, index: usize) -> u8 {
+        self[index]
+    }
+
+    fn set_pixel(&mut self, index: usize, value: u8) {
+        self[index] = value & 0x3;
+    }
+}
+
+#[cfg(not(feature = "packed-video"))]
+pub type Video = [u8; VIDEO_SIZE];
+
+#[cfg(feature = "packed-video")]
+pub type Video = BitVideo;
+
+#[cfg(not(feature = "packed-video"))]
+pub(crate) fn blank_video() -> Video {
+    [0; VIDEO_SIZE]
+}
+
+#[cfg(feature = "packed-video")]
+pub(crate) fn blank_video() -> Video {
+    BitVideo::new()
+}
+
+// one bit per pixel for each of the two planes
+#[cfg(feature = "packed-video")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitVideo {
+    planes: [[u64; VIDEO_SIZE / 64]; 2]
+}
+
+#[cfg(feature = "packed-video")]
+impl BitVideo {
+    pub fn new() -> Self {
+        BitVideo { planes: [[0; VIDEO_SIZE / 64]; 2] }
+    }
+}
+
+#[cfg(feature = "packed-video")]
+impl Default for BitVideo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "packed-video")]
+impl PixelBuffer for BitVideo {
+    fn get_pixel(&self, index: usize) -> u8 {
+        let (word, bit) = (index / 64, index % 64);
+        let plane1 = (self.planes[0][word] >> bit) & 1;
+        let plane2 = (self.planes[1][word] >> bit) & 1;
+        (plane1 | plane2 << 1) as u8
+    }
+
+    fn set_pixel(&mut self, index: usize, value: u8) {
+        let (word, bit) = (index / 64, index % 64);
+        for (plane, bits) in self.planes.iter_mut().enumerate() {
+            if value & (1 << plane) != 0 {
+                bits[word] |= 1 << bit;
+            } else {
+                bits[word] &= !(1 << bit);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::builder::RomBuilder;
+    use crate::chip8::{Chip8, InstructionSet};
+
+    // 200 RND-placed 9-row sprites from the font in both planes; the expected count and checksum
+    // were recorded from the byte build, and `cargo test --features packed-video` has to reproduce them
+    #[test]
+    fn draws_give_the_same_checksum_in_either_representation() {
+        let rom = RomBuilder::new()
+            .word(0xF301)
+            .rnd(0, 0xFF).rnd(1, 0xFF)
+            .ld_i(0x050)
+            .drw(0, 1, 9)
+            .jp(0x202)
+            .build();
+        let mut chip8 = Chip8::with_seed(0xC8);
+        chip8.instruction_set = InstructionSet::XoChip;
+        chip8.memory[0x200..0x200 + rom.len()].copy_from_slice(&rom);
+        for _ in 0..5 * 200 {
+            chip8.emulate_cycle().unwrap();
+        }
+
+        assert_eq!(chip8.lit_pixel_count(), 1882);
+        assert_eq!(chip8.video_checksum(), 0x71C0_4E0E);
+    }
+
+    // Video is BitVideo with packed-video and the byte array itself otherwise
+    #[test]
+    fn video_matches_byte_video() {
+        let mut rng = StdRng::seed_from_u64(0xC8);
+        let mut bytes = [0u8; VIDEO_SIZE];
+        let mut video: Video = blank_video();
+        for _ in 0..20_000 {
+            let (index, value) = (rng.random_range(0..VIDEO_SIZE), rng.random_range(0..4));
+            if rng.random() {
+                assert_eq!(bytes.xor_pixel(index, value), video.xor_pixel(index, value));
+            } else {
+                bytes.set_pixel(index, value);
+                video.set_pixel(index, value);
+            }
+        }
+        assert!((0..VIDEO_SIZE).all(|index| bytes.get_pixel(index) == video.get_pixel(index)));
+    }
+}
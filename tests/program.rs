@@ -0,0 +1,46 @@
+// a short program run end to end through emulate_cycle, checking where it leaves the machine
+use chip8::{Chip8, ErrorPolicy, RomBuilder};
+
+// sums 1..=5 into V0 in a loop, doubles it in a subroutine, then writes its BCD to 0x300
+// and draws the hundreds digit
+fn rom() -> Vec<u8> {
+    let subroutine = 0x21A;
+    RomBuilder::new()
+        .ld(0, 0)
+        .ld(1, 5)
+        .add_reg(0, 1)          // 0x204: loop
+        .add(1, 0xFF)
+        .se(1, 0)
+        .jp(0x204)
+        .call(subroutine)
+        .ld_i(0x300)
+        .ld_bcd(0)
+        .load(2)
+        .ld_font(0)
+        .drw(3, 3, 5)
+        .halt()                 // 0x218
+        .add_reg(0, 0)          // 0x21A: subroutine
+        .ret()
+        .build()
+}
+
+#[test]
+fn program_runs_end_to_end() {
+    let mut chip8 = Chip8::with_seed(0);
+    chip8.error_policy = ErrorPolicy::Strict;
+    chip8.load_rom_from_bytes(&rom()).unwrap();
+
+    while chip8.pc != 0x218 {
+        chip8.emulate_cycle().unwrap();
+        assert!(chip8.cycles < 100, "program never reached the halt");
+    }
+
+    // 2 setup + 5 loop passes of 4 (the last one's JP is skipped) + CALL/ADD/RET + 5
+    assert_eq!(chip8.cycles, 2 + 5 * 4 - 1 + 3 + 5);
+    assert_eq!(chip8.memory[0x300..0x303], [0, 3, 0]);
+    assert_eq!(chip8.registers[..3], [0, 3, 0]);
+    assert_eq!(chip8.registers[0xF], 0);
+    assert_eq!(chip8.index, 0x050);
+    assert_eq!(chip8.sp, 0);
+    assert_eq!(chip8.lit_pixel_count(), 14);
+}
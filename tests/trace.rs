@@ -0,0 +1,46 @@
+// generate_trace against the committed tests/golden/trace.txt. If an intended change
+// alters the trace, regenerate the file with `UPDATE_GOLDEN=1 cargo test --test trace`
+// and review the diff.
+use chip8::{RomBuilder, generate_trace};
+
+const GOLDEN: &str = "tests/golden/trace.txt";
+
+// a bit of everything the trace shows: registers, flags, I, the stack, timers, RND and a draw
+fn rom() -> Vec<u8> {
+    let subroutine = 0x21C;
+    RomBuilder::new()
+        .ld(0, 0xFE)
+        .add(0, 0x03)
+        .ld(1, 0x80)
+        .add_reg(1, 1)
+        .ld_dt(0)
+        .call(subroutine)
+        .rnd(2, 0x0F)
+        .ld_font(2)
+        .drw(3, 3, 5)
+        .shr(0, 0)
+        .ld_bcd(0)
+        .ld_i(0x300)
+        .store(2)
+        .halt()
+        .add(4, 1)
+        .ret()
+        .build()
+}
+
+fn trace_text() -> String {
+    generate_trace(&rom(), 32, 0xC8).iter().map(|line| format!("{}\n", line)).collect()
+}
+
+#[test]
+fn trace_matches_golden_file() {
+    let path = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), GOLDEN);
+    let actual = trace_text();
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, &actual).unwrap();
+    }
+
+    let expected = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(actual, expected, "trace differs from {}", GOLDEN);
+}